@@ -3,7 +3,8 @@ use std::collections::HashMap;
 #[derive(Debug)]
 enum Value {
     Number(u32),
-    Symbol(char),
+    // The symbol itself is only kept for debug output
+    Symbol(#[allow(dead_code)] char),
 }
 
 #[derive(Debug, Hash, Eq, PartialEq)]
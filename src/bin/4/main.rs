@@ -1,106 +1,421 @@
-use std::collections::HashMap;
+use advent_of_code::arg_value;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt,
+    ops::Range,
+    str::FromStr,
+};
+
+/// Numbers below this are stored in the bitset, anything larger falls back to a HashSet
+const BITSET_BITS: usize = 128;
+
+/// A set of card numbers. Card numbers are small in practice, so a fixed bitset makes
+/// membership checks a couple of bit operations. Larger numbers are still supported but are kept
+/// in a regular HashSet.
+#[derive(Debug, Default)]
+struct NumberSet {
+    bits: [u64; BITSET_BITS / 64],
+    large: HashSet<usize>,
+}
+
+impl NumberSet {
+    fn insert(&mut self, n: usize) {
+        if n < BITSET_BITS {
+            self.bits[n / 64] |= 1 << (n % 64);
+        } else {
+            self.large.insert(n);
+        }
+    }
+
+    fn contains(&self, n: usize) -> bool {
+        if n < BITSET_BITS {
+            self.bits[n / 64] & (1 << (n % 64)) != 0
+        } else {
+            self.large.contains(&n)
+        }
+    }
+}
+
+impl FromIterator<usize> for NumberSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut set = Self::default();
+        iter.into_iter().for_each(|n| set.insert(n));
+        set
+    }
+}
 
 #[derive(Debug)]
 struct Card {
-    index: usize,
-    winning_numbers: HashMap<usize, bool>,
-    numbers: Vec<usize>,
+    /// The id from the `Card N:` header
+    id: usize,
+    /// How many of our numbers are winning numbers. Computed once when the card is created, the
+    /// numbers themselves aren't needed after that
+    matches: usize,
 }
 
 impl Card {
-    fn winning_numbers(&self) -> usize {
-        self.numbers
+    fn matches(&self) -> usize {
+        self.matches
+    }
+
+    fn points(&self, scoring: Scoring) -> Option<usize> {
+        scoring.points(self.matches())
+    }
+}
+
+impl Card {
+    fn new(id: usize, winning_numbers: Vec<usize>, numbers: Vec<usize>) -> Self {
+        let winning_numbers: NumberSet = winning_numbers.into_iter().collect();
+        let matches = numbers
             .iter()
-            .filter(|n| self.winning_numbers.contains_key(n))
-            .count()
+            .filter(|n| winning_numbers.contains(**n))
+            .count();
+        Self { id, matches }
     }
+}
 
-    fn points(&self) -> usize {
-        let winning_numbers = self.winning_numbers();
-        if winning_numbers > 0 {
-            2_usize.pow(winning_numbers as u32 - 1)
-        } else {
-            0
+/// How many points a card is worth for its number of matches
+#[derive(Clone, Copy, Debug)]
+enum Scoring {
+    /// 1, 2, 4, 8, ... as in the puzzle
+    Doubling,
+    /// One point per match
+    Linear,
+    /// 1, 2, 3, 5, 8, ...
+    Fibonacci,
+}
+
+impl Scoring {
+    /// Points for a card with this many matches, None if they don't fit in a usize
+    fn points(self, matches: usize) -> Option<usize> {
+        if matches == 0 {
+            return Some(0);
+        }
+        match self {
+            Scoring::Doubling => 2_usize.checked_pow(u32::try_from(matches - 1).ok()?),
+            Scoring::Linear => Some(matches),
+            Scoring::Fibonacci => (1..matches)
+                .try_fold((1_usize, 1_usize), |(a, b), _| Some((b, a.checked_add(b)?)))
+                .map(|(_, b)| b),
         }
     }
 }
 
-impl Card {
-    fn new(index: usize, winning_numbers: Vec<usize>, numbers: Vec<usize>) -> Self {
-        Self {
-            index,
-            winning_numbers: winning_numbers.into_iter().map(|n| (n, true)).collect(),
-            numbers,
+impl FromStr for Scoring {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "doubling" => Ok(Scoring::Doubling),
+            "linear" => Ok(Scoring::Linear),
+            "fibonacci" => Ok(Scoring::Fibonacci),
+            _ => Err(format!("Unknown scoring {name}")),
         }
     }
 }
 
+/// Which cards receive the copies won by a card with n matches
+#[derive(Clone, Copy, Debug)]
+enum CopyRule {
+    /// The next n cards, ignoring any past the end of the table as in the puzzle
+    Capped,
+    /// The next n cards, continuing from the first card when running past the end of the table.
+    /// A card never copies itself, so n is limited to the number of other cards
+    Wrapping,
+}
+
+impl CopyRule {
+    /// The (0-based) card positions that each get one copy per instance of the card at
+    /// `position`. The second range is only used for copies that wrapped around
+    fn targets(self, position: usize, matches: usize, len: usize) -> [Range<usize>; 2] {
+        let first = position + 1;
+        match self {
+            CopyRule::Capped => [first..(first + matches).min(len), 0..0],
+            CopyRule::Wrapping => {
+                let last = first + matches.min(len - 1);
+                if last <= len {
+                    [first..last, 0..0]
+                } else {
+                    [first..len, 0..(last - len)]
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for CopyRule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "capped" => Ok(CopyRule::Capped),
+            "wrapping" => Ok(CopyRule::Wrapping),
+            _ => Err(format!("Unknown copy rule {name}")),
+        }
+    }
+}
+
+/// How a single card took part in resolving the game
+#[derive(Debug)]
+struct CardTrace {
+    /// Instances held when the card was processed, each of which wins one copy of every target
+    instances: usize,
+    /// Positions of the cards that were copied, as returned by CopyRule::targets
+    targets: [Range<usize>; 2],
+}
+
 #[derive(Default)]
 struct Game {
-    known_cards: HashMap<usize, usize>,
+    /// Number of instances (original plus copies) held of each card, in card id order
+    instances: Vec<usize>,
+    /// Per card trace of the resolution, in card id order
+    trace: Vec<CardTrace>,
 }
 
 impl Game {
-    // Returns the total cards added by this card (including itself)
-    fn resolve_card(&mut self, cards: &[Card], card: &Card) -> usize {
-        if let Some(value) = self.known_cards.get(&card.index) {
-            *value
-        } else {
-            let mut sum = 1;
-            if let Some(card_copies) =
-                cards.get((card.index + 1)..(card.index + 1 + card.winning_numbers()))
-            {
-                card_copies.iter().for_each(|card_copy| {
-                    sum += self.resolve_card(cards, card_copy);
-                });
-            };
-            self.known_cards.insert(card.index, sum);
-            sum
+    /// Resolve the instances of every card without recursion. Cards are processed once in
+    /// order, and copies to later cards are kept as a running total of pending copies (a
+    /// difference array), which keeps this linear in the number of cards. Copies that wrap
+    /// around to cards that were already processed still count as instances, but don't win
+    /// any further copies (that could go on forever).
+    fn resolve_cards(&mut self, cards: &[Card], copy_rule: CopyRule) {
+        // pending[i] is the change in copies-per-card that starts (or stops) at position i.
+        // Cards are validated by parse_cards to be numbered 1..=cards.len() in order
+        let mut pending = vec![0_isize; cards.len() + 1];
+        // Same as pending, but for copies landing on cards that were already processed
+        let mut wrapped = vec![0_isize; cards.len() + 1];
+        let mut running = 0_isize;
+        self.trace = cards
+            .iter()
+            .map(|card| {
+                let position = card.id - 1;
+                running += pending[position];
+                let instances = 1 + running as usize;
+                let targets = copy_rule.targets(position, card.matches(), cards.len());
+                for targets in targets.iter() {
+                    if !targets.is_empty() {
+                        let diff = if targets.start > position {
+                            &mut pending
+                        } else {
+                            &mut wrapped
+                        };
+                        diff[targets.start] += instances as isize;
+                        diff[targets.end] -= instances as isize;
+                    }
+                }
+                CardTrace { instances, targets }
+            })
+            .collect();
+        self.instances = self.trace.iter().map(|trace| trace.instances).collect();
+
+        let mut running = 0_isize;
+        self.instances
+            .iter_mut()
+            .zip(wrapped.iter())
+            .for_each(|(instances, change)| {
+                running += change;
+                *instances += running as usize;
+            });
+    }
+
+    /// Instances of each card after all copies have been won
+    fn instances(&self) -> &[usize] {
+        &self.instances
+    }
+
+    fn total(&self) -> usize {
+        self.instances.iter().sum()
+    }
+
+    /// Print how many instances every card ended up with, which cards it copied and a histogram
+    /// of the instance counts
+    fn print_trace(&self) {
+        for (position, (trace, instances)) in self.trace.iter().zip(&self.instances).enumerate() {
+            let copied: Vec<String> = trace
+                .targets
+                .iter()
+                .filter(|targets| !targets.is_empty())
+                // Positions are 0-based, ids 1-based
+                .map(|targets| format!("{}-{}", targets.start + 1, targets.end))
+                .collect();
+            let copies: usize = trace.targets.iter().map(|targets| targets.len()).sum();
+            if copied.is_empty() {
+                println!("Card {}: {instances} instances, no copies", position + 1);
+            } else {
+                println!(
+                    "Card {}: {instances} instances, {} copies each of cards {} ({} copies)",
+                    position + 1,
+                    trace.instances,
+                    copied.join(", "),
+                    trace.instances * copies
+                );
+            }
+        }
+
+        // Bucket instance counts by powers of two, instance counts grow quickly
+        let mut histogram: Vec<usize> = vec![];
+        for instances in self.instances.iter() {
+            let bucket = instances.ilog2() as usize;
+            if histogram.len() <= bucket {
+                histogram.resize(bucket + 1, 0);
+            }
+            histogram[bucket] += 1;
+        }
+        println!("Instances histogram:");
+        let widest = histogram.iter().max().copied().unwrap_or(1);
+        for (bucket, count) in histogram.iter().enumerate() {
+            let from = 1_usize << bucket;
+            let to = (from << 1) - 1;
+            // Scale bars to at most 50 characters
+            let bar = "#".repeat((count * 50).div_ceil(widest));
+            println!("{from:>10}-{to:<10} {count:>6} {bar}");
         }
     }
 }
 
+#[derive(Debug)]
+enum CardError {
+    /// The line isn't of the form `Card N: winning numbers | numbers`
+    Malformed {
+        line: usize,
+    },
+    InvalidNumber {
+        line: usize,
+        value: String,
+    },
+    DuplicateId {
+        id: usize,
+        first_line: usize,
+        line: usize,
+    },
+    /// Card ids have to run from 1 without any gaps
+    MissingId(usize),
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardError::Malformed { line } => {
+                write!(f, "line {line}: expected `Card N: winning | numbers`")
+            }
+            CardError::InvalidNumber { line, value } => {
+                write!(f, "line {line}: `{value}` is not a valid number")
+            }
+            CardError::DuplicateId {
+                id,
+                first_line,
+                line,
+            } => write!(
+                f,
+                "line {line}: card {id} was already defined on line {first_line}"
+            ),
+            CardError::MissingId(id) => write!(f, "card {id} is missing"),
+        }
+    }
+}
+
+fn parse_numbers(numbers_str: &str, line: usize) -> Result<Vec<usize>, CardError> {
+    numbers_str
+        .split_whitespace()
+        .map(|value| {
+            value.parse().map_err(|_| CardError::InvalidNumber {
+                line,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse all cards, returning them sorted by id. Ids have to be unique and contiguous from 1 as
+/// the copies a card wins are the cards with the following ids.
+fn parse_cards(input: &str) -> Result<Vec<Card>, CardError> {
+    let mut cards = vec![];
+    // Line each id was first seen on, to point at both lines for duplicates
+    let mut id_lines: HashMap<usize, usize> = HashMap::new();
+    for (line_index, line) in input.lines().enumerate() {
+        let line_number = line_index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (header, game) = line
+            .split_once(':')
+            .ok_or(CardError::Malformed { line: line_number })?;
+        let id_str = header
+            .strip_prefix("Card")
+            .ok_or(CardError::Malformed { line: line_number })?
+            .trim();
+        let id: usize = id_str.parse().map_err(|_| CardError::InvalidNumber {
+            line: line_number,
+            value: id_str.to_string(),
+        })?;
+        let (winning_str, numbers_str) = game
+            .split_once('|')
+            .ok_or(CardError::Malformed { line: line_number })?;
+
+        match id_lines.entry(id) {
+            Entry::Occupied(o) => {
+                return Err(CardError::DuplicateId {
+                    id,
+                    first_line: *o.get(),
+                    line: line_number,
+                })
+            }
+            Entry::Vacant(v) => {
+                v.insert(line_number);
+            }
+        }
+
+        cards.push(Card::new(
+            id,
+            parse_numbers(winning_str, line_number)?,
+            parse_numbers(numbers_str, line_number)?,
+        ));
+    }
+
+    cards.sort_by_key(|card| card.id);
+    // With unique ids sorted, any gap shows up as the first card whose id isn't its position
+    if let Some(missing) = (1..=cards.len())
+        .zip(cards.iter())
+        .find_map(|(expected, card)| (card.id != expected).then_some(expected))
+    {
+        return Err(CardError::MissingId(missing));
+    }
+    Ok(cards)
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let scoring: Scoring = arg_value(&args, "--scoring").unwrap_or(Scoring::Doubling);
+    let copy_rule: CopyRule = arg_value(&args, "--copies").unwrap_or(CopyRule::Capped);
+
     let input = include_str!("./input");
     println!("{input}");
-    let lines: Vec<_> = input.split('\n').filter(|line| !line.is_empty()).collect();
-
-    let cards: Vec<_> = lines
-        .into_iter()
-        .enumerate()
-        .flat_map(|(index, line)| {
-            line.split_once(':')
-                .and_then(|(_, game)| game.split_once('|'))
-                .map(|(winning_str, numbers_str)| {
-                    Card::new(
-                        index,
-                        winning_str
-                            .split_whitespace()
-                            // Beware flat_mapping results will simply remove errors
-                            .flat_map(str::parse::<usize>)
-                            .collect(),
-                        numbers_str
-                            .split_whitespace()
-                            .flat_map(str::parse::<usize>)
-                            .collect(),
-                    )
-                })
-        })
-        .collect();
+
+    let cards = parse_cards(input).unwrap_or_else(|error| panic!("Invalid cards: {error}"));
     println!("{cards:#?}");
 
     // Part one
-    let card_points: Vec<usize> = cards.iter().map(Card::points).collect();
+    let card_points: Vec<usize> = cards
+        .iter()
+        .map(|card| {
+            card.points(scoring)
+                .unwrap_or_else(|| panic!("Card {} is worth too many points", card.id))
+        })
+        .collect();
     println!("{card_points:?}");
-    let sum: usize = card_points.iter().sum();
+    let sum = card_points
+        .iter()
+        .try_fold(0_usize, |sum, points| sum.checked_add(*points))
+        .expect("Sum of points overflows");
     println!("{sum}");
 
     // Part two
     let mut game = Game::default();
-    let resolved_cards: Vec<_> = cards
-        .iter()
-        .map(|card| game.resolve_card(&cards, card))
-        .collect();
-    let sum: usize = resolved_cards.iter().sum();
+    game.resolve_cards(&cards, copy_rule);
+    println!("{:?}", game.instances());
+    if args.iter().any(|arg| arg == "--trace") {
+        game.print_trace();
+    }
+    let sum = game.total();
     println!("{sum}");
 }
@@ -1,6 +1,7 @@
-use rayon::prelude::*;
+use advent_of_code::arg_value;
+use std::collections::{HashMap, VecDeque};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct Range {
     dst: usize,
     src: usize,
@@ -9,83 +10,531 @@ struct Range {
 
 impl Range {
     fn in_src_range(&self, src: usize) -> bool {
-        src >= self.src && src < self.src + self.range
+        src >= self.src && src < self.src_end()
+    }
+
+    fn src_end(&self) -> usize {
+        self.src + self.range
+    }
+
+    /// The part of this range covering the sources in src, if any
+    fn restrict(&self, src: &std::ops::Range<usize>) -> Option<Range> {
+        let start = src.start.max(self.src);
+        let end = src.end.min(self.src_end());
+        (start < end).then(|| Range {
+            dst: self.dst + (start - self.src),
+            src: start,
+            range: end - start,
+        })
     }
 }
 
+/// Problems with the ranges of a map as listed in the input. Ranges are numbered from 1 in file
+/// order
+#[derive(Debug)]
+enum RangeIssue {
+    /// Both ranges map some of the same sources, the first one listed wins
+    SrcOverlap(usize, usize),
+    /// Both ranges map onto some of the same destinations
+    DstOverlap(usize, usize),
+    /// The end of the source or destination range doesn't fit in a usize, the range is cut short
+    Overflow(usize),
+    /// The range doesn't map anything
+    Empty(usize),
+}
+
+impl std::fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RangeIssue::SrcOverlap(a, b) => write!(f, "range {a} and {b} overlap in source"),
+            RangeIssue::DstOverlap(a, b) => write!(f, "range {a} and {b} overlap in destination"),
+            RangeIssue::Overflow(a) => write!(f, "range {a} overflows"),
+            RangeIssue::Empty(a) => write!(f, "range {a} is empty"),
+        }
+    }
+}
+
+impl RangeIssue {
+    /// Check the ranges of a map in file order
+    fn find(ranges: &[Range]) -> Vec<RangeIssue> {
+        let overlaps = |a_start: usize, a_len: usize, b_start: usize, b_len: usize| {
+            // Compare in u128 so that overflowing ranges can be checked too
+            let (a_start, b_start) = (a_start as u128, b_start as u128);
+            a_len > 0
+                && b_len > 0
+                && a_start < b_start + b_len as u128
+                && b_start < a_start + a_len as u128
+        };
+        let mut issues = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            if range.range == 0 {
+                issues.push(RangeIssue::Empty(i + 1));
+            }
+            if range.src.checked_add(range.range).is_none()
+                || range.dst.checked_add(range.range).is_none()
+            {
+                issues.push(RangeIssue::Overflow(i + 1));
+            }
+            for (j, other) in ranges.iter().enumerate().skip(i + 1) {
+                if overlaps(range.src, range.range, other.src, other.range) {
+                    issues.push(RangeIssue::SrcOverlap(i + 1, j + 1));
+                }
+                if overlaps(range.dst, range.range, other.dst, other.range) {
+                    issues.push(RangeIssue::DstOverlap(i + 1, j + 1));
+                }
+            }
+        }
+        issues
+    }
+}
+
+/// A piecewise linear function made up of custom mappings, anything not covered by a range maps
+/// to itself. Ranges are kept sorted by src and never overlap, so lookups can binary search.
 #[derive(Debug)]
 struct Map {
+    /// Category converted from, eg. `seed` for a `seed-to-soil` map
+    from: String,
+    /// Category converted to, eg. `soil` for a `seed-to-soil` map
+    to: String,
     ranges: Vec<Range>,
+    /// Problems found with the ranges as listed in the input
+    issues: Vec<RangeIssue>,
 }
 
 impl Map {
+    /// Build a map from ranges in file order. Where source ranges overlap, the range listed first
+    /// takes precedence. Ranges that would overflow are cut short and empty ones are dropped,
+    /// both are recorded as issues.
+    fn new(from: &str, to: &str, ranges: Vec<Range>) -> Self {
+        let issues = RangeIssue::find(&ranges);
+        let mut sorted: Vec<Range> = vec![];
+        for mut range in ranges {
+            range.range = range
+                .range
+                .min(usize::MAX - range.src)
+                .min(usize::MAX - range.dst);
+            if range.range == 0 {
+                continue;
+            }
+            // Only keep the parts of the range that no earlier range covers
+            let mut uncovered: Vec<_> = std::iter::once(range.src..range.src_end()).collect();
+            for existing in sorted.iter() {
+                uncovered = uncovered
+                    .into_iter()
+                    .flat_map(|part| {
+                        if existing.restrict(&part).is_some() {
+                            vec![part.start..existing.src, existing.src_end()..part.end]
+                        } else {
+                            vec![part]
+                        }
+                    })
+                    .filter(|part| !part.is_empty())
+                    .collect();
+            }
+            sorted.extend(uncovered.iter().flat_map(|part| range.restrict(part)));
+        }
+        sorted.sort_by_key(|range| range.src);
+        Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            ranges: sorted,
+            issues,
+        }
+    }
+
+    /// A map converting a category to itself
+    fn identity(category: &str) -> Self {
+        Self::new(category, category, vec![])
+    }
+
     /// If custom mapping, do it, else return src as new dst
     fn get_dst(&self, src: usize) -> usize {
-        for range in self.ranges.iter() {
-            if range.in_src_range(src) {
-                let step = src - range.src;
-                return range.dst + step;
-            };
+        // First range ending after src is the only one that could contain it
+        let i = self.ranges.partition_point(|range| range.src_end() <= src);
+        match self.ranges.get(i) {
+            Some(range) if range.in_src_range(src) => range.dst + (src - range.src),
+            _ => src,
+        }
+    }
+
+    /// All sources that map to dst, the inverse of get_dst. Well formed maps are injective, but
+    /// nothing stops two ranges from mapping onto the same destinations
+    fn get_src(&self, dst: usize) -> Vec<usize> {
+        let mut srcs: Vec<usize> = self
+            .ranges
+            .iter()
+            .filter(|range| dst >= range.dst && dst < range.dst + range.range)
+            .map(|range| range.src + (dst - range.dst))
+            // dst maps to itself if no range covers it
+            .chain(std::iter::once(dst))
+            .filter(|&src| self.get_dst(src) == dst)
+            .collect();
+        srcs.sort();
+        srcs.dedup();
+        srcs
+    }
+
+    /// Split an interval of sources into pieces that are each mapped by a single range, including
+    /// the pieces that map to themselves, in src order
+    fn pieces(&self, src: std::ops::Range<usize>) -> Vec<Range> {
+        let mut pieces = vec![];
+        let mut position = src.start;
+        let first = self
+            .ranges
+            .partition_point(|range| range.src_end() <= src.start);
+        for range in self.ranges[first..].iter() {
+            if range.src >= src.end {
+                break;
+            }
+            if position < range.src {
+                pieces.push(Range {
+                    dst: position,
+                    src: position,
+                    range: range.src - position,
+                });
+            }
+            if let Some(piece) = range.restrict(&src) {
+                position = piece.src_end();
+                pieces.push(piece);
+            }
+        }
+        if position < src.end {
+            pieces.push(Range {
+                dst: position,
+                src: position,
+                range: src.end - position,
+            });
+        }
+        pieces
+    }
+
+    /// Map a whole interval of sources at once. The interval is split at the boundaries of the
+    /// custom mappings, so the result is one interval per piece.
+    fn get_dst_ranges(&self, src: std::ops::Range<usize>) -> Vec<std::ops::Range<usize>> {
+        self.pieces(src)
+            .into_iter()
+            .map(|piece| piece.dst..(piece.dst + piece.range))
+            .collect()
+    }
+
+    /// Fuse this map and next into a single map, so that
+    /// `self.compose(next).get_dst(x) == next.get_dst(self.get_dst(x))`
+    fn compose(&self, next: &Map) -> Map {
+        let mut ranges: Vec<Range> = vec![];
+        for piece in self.pieces(0..usize::MAX) {
+            for next_piece in next.pieces(piece.dst..(piece.dst + piece.range)) {
+                let range = Range {
+                    dst: next_piece.dst,
+                    src: piece.src + (next_piece.src - piece.dst),
+                    range: next_piece.range,
+                };
+                // Pieces mapping to themselves are implied
+                if range.src == range.dst {
+                    continue;
+                }
+                // Merge with the previous range if it simply continues it
+                match ranges.last_mut() {
+                    Some(last)
+                        if last.src_end() == range.src && last.dst + last.range == range.dst =>
+                    {
+                        last.range += range.range
+                    }
+                    _ => ranges.push(range),
+                }
+            }
+        }
+        // Pieces are produced in src order, so ranges are already sorted
+        Map {
+            from: self.from.clone(),
+            to: next.to.clone(),
+            ranges,
+            issues: vec![],
+        }
+    }
+
+    /// The custom mappings making up this map, sorted by src
+    fn segments(&self) -> &[Range] {
+        &self.ranges
+    }
+}
+
+/// All maps of the almanac, keyed by the categories they convert between
+#[derive(Debug, Default)]
+struct Almanac {
+    maps: HashMap<(String, String), Map>,
+    /// Categories each category can be converted to directly
+    graph: HashMap<String, Vec<String>>,
+}
+
+impl Almanac {
+    fn insert(&mut self, map: Map) {
+        self.graph
+            .entry(map.from.clone())
+            .or_default()
+            .push(map.to.clone());
+        self.maps.insert((map.from.clone(), map.to.clone()), map);
+    }
+
+    /// The maps to apply in order to convert from one category to another, using the fewest
+    /// conversions. The order the maps were listed in doesn't matter.
+    fn path(&self, from: &str, to: &str) -> Option<Vec<&Map>> {
+        // Breadth first search, remembering where we came from to rebuild the path
+        let mut came_from: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(category) = queue.pop_front() {
+            if category == to {
+                let mut path = vec![];
+                let mut current = to;
+                while current != from {
+                    let previous = came_from[current];
+                    path.push(&self.maps[&(previous.to_string(), current.to_string())]);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.graph.get(category).into_iter().flatten() {
+                if next != from && !came_from.contains_key(next.as_str()) {
+                    came_from.insert(next, category);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Maps in a stable order, for output
+    fn sorted_maps(&self) -> Vec<&Map> {
+        let mut maps: Vec<&Map> = self.maps.values().collect();
+        maps.sort_by_key(|map| (&map.from, &map.to));
+        maps
+    }
+
+    /// A single map converting from one category to another
+    fn conversion(&self, from: &str, to: &str) -> Option<Map> {
+        self.path(from, to).map(|path| {
+            path.into_iter()
+                .fold(Map::identity(from), |fused, map| fused.compose(map))
+        })
+    }
+}
+
+/// Where and why the almanac couldn't be parsed
+#[derive(Debug)]
+struct ParseError {
+    /// 1-based line number
+    line: usize,
+    /// Header of the section the line belongs to, if any
+    section: Option<String>,
+    message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.section {
+            Some(section) => write!(f, "line {} ({section}): {}", self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
         }
-        src
     }
 }
 
-fn parse_map(lines: &mut Vec<&str>) -> Map {
-    lines.pop();
-    let mut ranges = vec![];
-    while let Some(line) = lines.pop() {
+/// A map section being parsed, ranges are added as their lines are read
+struct Section<'a> {
+    header: &'a str,
+    from: &'a str,
+    to: &'a str,
+    ranges: Vec<Range>,
+}
+
+/// Parse the almanac front to back, one line at a time. Blank lines, CRLF line endings and any
+/// amount of whitespace between numbers are accepted.
+fn parse_almanac(input: &str) -> Result<(Vec<usize>, Almanac), ParseError> {
+    let mut seeds: Option<Vec<usize>> = None;
+    let mut almanac = Almanac::default();
+    let mut section: Option<Section> = None;
+    // Line each map header was first seen on, a second map between the same categories would
+    // otherwise silently replace the first
+    let mut header_lines: HashMap<(&str, &str), usize> = HashMap::new();
+
+    for (line_index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        let header = section.as_ref().map(|section| section.header);
+        let error = |message: String| ParseError {
+            line: line_index + 1,
+            section: header.map(str::to_string),
+            message,
+        };
+        let parse_numbers = |numbers: &str| -> Result<Vec<usize>, ParseError> {
+            numbers
+                .split_whitespace()
+                .map(|n| {
+                    n.parse::<usize>()
+                        .map_err(|_| error(format!("`{n}` is not a valid number")))
+                })
+                .collect()
+        };
+
         if line.is_empty() {
-            break;
-        } else {
-            let mut numbers = line.split(' ').map(|n| n.parse::<usize>().unwrap());
-            ranges.push(Range {
-                dst: numbers.next().unwrap(),
-                src: numbers.next().unwrap(),
-                range: numbers.next().unwrap(),
+            continue;
+        } else if let Some(numbers) = line.strip_prefix("seeds:") {
+            if seeds.is_some() {
+                return Err(error("seeds are listed twice".to_string()));
+            }
+            seeds = Some(parse_numbers(numbers)?);
+        } else if let Some(categories) = line.strip_suffix("map:") {
+            // Header is eg. `seed-to-soil map:`
+            let (from, to) = categories
+                .trim_end()
+                .split_once("-to-")
+                .ok_or_else(|| error(format!("expected `<from>-to-<to> map:`, got `{line}`")))?;
+            if let Some(first_line) = header_lines.insert((from, to), line_index + 1) {
+                return Err(ParseError {
+                    line: line_index + 1,
+                    section: Some(line.to_string()),
+                    message: format!("{from} to {to} is already mapped on line {first_line}"),
+                });
+            }
+            if let Some(finished) = section.take() {
+                almanac.insert(Map::new(finished.from, finished.to, finished.ranges));
+            }
+            section = Some(Section {
+                header: line,
+                from,
+                to,
+                ranges: vec![],
             });
+        } else {
+            let numbers = parse_numbers(line)?;
+            let Some(current) = section.as_mut() else {
+                return Err(error(format!(
+                    "expected seeds or a map header, got `{line}`"
+                )));
+            };
+            match numbers[..] {
+                [dst, src, range] => current.ranges.push(Range { dst, src, range }),
+                _ => {
+                    return Err(error(format!(
+                        "expected three numbers, got {}",
+                        numbers.len()
+                    )))
+                }
+            }
+        }
+    }
+    if let Some(finished) = section {
+        almanac.insert(Map::new(finished.from, finished.to, finished.ranges));
+    }
+
+    let seeds = seeds.ok_or(ParseError {
+        line: 1,
+        section: None,
+        message: "no seeds listed".to_string(),
+    })?;
+    Ok((seeds, almanac))
+}
+
+/// Every segment of every map as CSV, including the gaps that map to themselves
+fn segments_csv(almanac: &Almanac) -> String {
+    let mut csv = String::from("from,to,src_start,src_end,dst_start,dst_end,identity\n");
+    for map in almanac.sorted_maps() {
+        for piece in map.pieces(0..usize::MAX) {
+            csv += &format!(
+                "{},{},{},{},{},{},{}\n",
+                map.from,
+                map.to,
+                piece.src,
+                piece.src_end(),
+                piece.dst,
+                piece.dst + piece.range,
+                piece.src == piece.dst
+            );
         }
     }
-    Map { ranges }
+    csv
 }
 
-fn parse_maps(lines: &mut Vec<&str>) -> Vec<Map> {
-    let mut maps = vec![];
-    while !lines.is_empty() {
-        maps.push(parse_map(lines));
+/// Graphviz diagram of the categories, with each map labelled by its number of segments
+fn categories_dot(almanac: &Almanac) -> String {
+    let mut dot = String::from("digraph almanac {\n    rankdir=LR;\n");
+    for map in almanac.sorted_maps() {
+        dot += &format!(
+            "    \"{}\" -> \"{}\" [label=\"{} segments\"];\n",
+            map.from,
+            map.to,
+            map.pieces(0..usize::MAX).len()
+        );
+    }
+    dot += "}\n";
+    dot
+}
+
+/// Start and end point of every segment of a map as CSV, ready to plot as line segments
+fn plot_csv(map: &Map) -> String {
+    let mut csv = format!("{},{}\n", map.from, map.to);
+    for piece in map.pieces(0..usize::MAX) {
+        csv += &format!("{},{}\n", piece.src, piece.dst);
+        csv += &format!("{},{}\n", piece.src_end() - 1, piece.dst + piece.range - 1);
+    }
+    csv
+}
+
+/// Print the issues found in every map, panicking if there are any and `strict` is set
+fn validate(almanac: &Almanac, strict: bool) {
+    let mut issue_count = 0;
+    for map in almanac.sorted_maps() {
+        for issue in map.issues.iter() {
+            println!("{}-to-{} map: {issue}", map.from, map.to);
+            issue_count += 1;
+        }
+    }
+    if strict && issue_count > 0 {
+        panic!("Rejecting almanac with {issue_count} issues");
     }
-    maps
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
     let input = include_str!("./input");
-    let mut lines: Vec<_> = input.split('\n').collect();
-    // Pop off extra newline at end of input
-    lines.pop();
-
-    // Reverse lines so we can .pop()
-    lines.reverse();
-
-    let seeds: Vec<usize> = {
-        let (_, seeds) = lines.pop().unwrap().split_once(": ").unwrap();
-        seeds
-            .split(' ')
-            .map(|n| n.parse::<usize>().unwrap())
-            .collect()
-    };
+    let (seeds, almanac) =
+        parse_almanac(input).unwrap_or_else(|error| panic!("Invalid almanac: {error}"));
     println!("{seeds:?}");
 
-    lines.pop();
-    let maps = parse_maps(&mut lines);
+    validate(&almanac, args.iter().any(|arg| arg == "--strict"));
+    let maps = almanac
+        .path("seed", "location")
+        .expect("No conversion from seed to location");
     println!("{maps:#?}");
 
-    // There shouldnt be any more input
-    assert!(lines.is_empty());
+    // Fuse the whole chain into a single seed-to-location map, so each lookup is one binary
+    // search rather than a walk through every map
+    let seed_to_location = almanac.conversion("seed", "location").unwrap();
+    if args.iter().any(|arg| arg == "--fused") {
+        for segment in seed_to_location.segments() {
+            println!(
+                "seeds {}..{} -> locations {}..{}",
+                segment.src,
+                segment.src_end(),
+                segment.dst,
+                segment.dst + segment.range
+            );
+        }
+    }
+
+    // Exports, eg. `--csv segments.csv --dot almanac.dot --plot seed-to-location.csv`
+    if let Some(path) = arg_value::<String>(&args, "--csv") {
+        std::fs::write(path, segments_csv(&almanac)).unwrap();
+    }
+    if let Some(path) = arg_value::<String>(&args, "--dot") {
+        std::fs::write(path, categories_dot(&almanac)).unwrap();
+    }
+    if let Some(path) = arg_value::<String>(&args, "--plot") {
+        std::fs::write(path, plot_csv(&seed_to_location)).unwrap();
+    }
 
     // Part one
     let min_location = seeds
         .iter()
-        .map(|seed| maps.iter().fold(*seed, |src, map| map.get_dst(src)))
+        .map(|seed| seed_to_location.get_dst(*seed))
         .min();
 
     println!("min_location: {min_location:?}");
@@ -101,18 +550,52 @@ fn main() {
         })
         .collect();
 
+    // Push whole intervals through the map rather than every single seed. The lowest location
+    // is the start of one of the resulting intervals
     let min_range_location = seed_ranges
-        // Use .par_iter() from Rayon to parallelize
-        .par_iter()
-        .flat_map(|range| {
-            range
-                .clone()
-                .map(|seed| maps.iter().fold(seed, |src, map| map.get_dst(src)))
-                .collect::<Vec<_>>()
-        })
-        // Calling .min() on ParallelIterator reduces memory footprint vs. .collect() all locations
-        // in a Vec and then doing .min()
+        .iter()
+        .cloned()
+        .flat_map(|range| seed_to_location.get_dst_ranges(range))
+        .map(|range| range.start)
         .min();
 
     println!("min_range_location: {min_range_location:?}");
+
+    // Trace a location back through every map to the seeds producing it
+    if let Some(location) = arg_value::<usize>(&args, "--trace-location") {
+        let mut values = vec![location];
+        println!("location: {values:?}");
+        for map in maps.iter().rev() {
+            values = values.iter().flat_map(|&dst| map.get_src(dst)).collect();
+            values.sort();
+            values.dedup();
+            println!("{}: {values:?}", map.from);
+        }
+        for seed in values {
+            let listed = seeds.contains(&seed);
+            let in_range = seed_ranges.iter().any(|range| range.contains(&seed));
+            println!("seed {seed}: in seed list {listed}, in seed ranges {in_range}");
+        }
+    }
+
+    // Any conversion between two categories, eg. `--path soil humidity`
+    if let Some(i) = args.iter().position(|arg| arg == "--path") {
+        let (Some(from), Some(to)) = (args.get(i + 1), args.get(i + 2)) else {
+            panic!("Expected two categories for --path, eg. `--path soil humidity`");
+        };
+        match almanac.path(from, to) {
+            Some(path) => {
+                let categories: Vec<&str> = std::iter::once(from.as_str())
+                    .chain(path.iter().map(|map| map.to.as_str()))
+                    .collect();
+                let conversion = almanac.conversion(from, to).unwrap();
+                println!(
+                    "{} ({} segments)",
+                    categories.join(" -> "),
+                    conversion.segments().len()
+                );
+            }
+            None => println!("No conversion from {from} to {to}"),
+        }
+    }
 }
@@ -1,61 +1,386 @@
+use advent_of_code::arg_value;
+use std::ops::RangeInclusive;
+
+/// Race numbers are u128 so that combined races much longer than the puzzle's still fit
 #[derive(Debug)]
 struct Race {
-    time: usize,
-    distance: usize,
+    time: u128,
+    distance: u128,
 }
 
 impl Race {
-    fn calculate_distance(&self, charge_time: usize) -> usize {
+    /// Distance travelled, None if it doesn't fit in a u128
+    fn calculate_distance(&self, charge_time: u128) -> Option<u128> {
         let speed = charge_time;
         let remaining_time = self.time - charge_time;
-        speed * remaining_time
+        speed.checked_mul(remaining_time)
+    }
+
+    fn wins(&self, charge_time: u128) -> bool {
+        // A distance too large for a u128 beats any record
+        self.calculate_distance(charge_time)
+            .is_none_or(|distance| distance > self.distance)
+    }
+
+    /// An estimate of the lowest winning charge time. Winning means c·(t−c) > d, so the winning
+    /// charge times lie strictly between the roots of c² − tc + d, (t ± √(t² − 4d)) / 2. Where
+    /// t² doesn't fit in a u128, binary search for it instead, distance only grows up to t / 2.
+    fn lowest_winning_estimate(&self) -> u128 {
+        match (
+            self.time.checked_mul(self.time),
+            self.distance.checked_mul(4),
+        ) {
+            // A negative discriminant saturates to t / 2, which is then found not to win
+            (Some(square), Some(four_distance)) => {
+                (self.time - square.saturating_sub(four_distance).isqrt()) / 2
+            }
+            // 4d overflowing while t² fits means t² < 4d, so no charge time wins
+            (Some(_), None) => self.time / 2,
+            (None, _) => first_where(0, self.time / 2, |c| self.wins(c)),
+        }
+    }
+
+    /// The charge times that result in a win, if any. The estimate of the lowest winning charge
+    /// time is corrected by checking the boundary. Distances are symmetric around t / 2, which
+    /// gives the upper end.
+    fn winning_charge_interval(&self) -> Option<RangeInclusive<u128>> {
+        let mut lowest = self.lowest_winning_estimate();
+        while lowest > 0 && self.wins(lowest - 1) {
+            lowest -= 1;
+        }
+        while lowest <= self.time / 2 && !self.wins(lowest) {
+            lowest += 1;
+        }
+        (lowest <= self.time / 2).then(|| lowest..=(self.time - lowest))
     }
 
     /// Count the charge times that results in a win
-    fn winning_charge_count(&self) -> usize {
+    fn winning_charge_count(&self) -> u128 {
+        self.winning_charge_interval()
+            .map_or(0, |interval| interval.end() - interval.start() + 1)
+    }
+
+    /// Count the charge times that results in a win by trying every one of them. Slow, but kept
+    /// as a reference to check winning_charge_count against
+    #[cfg(test)]
+    fn winning_charge_count_brute_force(&self) -> u128 {
         (0..self.time)
-            .filter(|charge_time| self.calculate_distance(*charge_time) > self.distance)
-            .count()
+            .filter(|charge_time| self.wins(*charge_time))
+            .count() as u128
+    }
+}
+
+/// How a boat moves once released. The puzzle's boat gains 1 mm/ms of speed per millisecond
+/// charged, has no top speed and never slows down.
+#[derive(Debug)]
+struct Physics {
+    /// Speed gained per millisecond of charging
+    acceleration: u128,
+    max_speed: Option<u128>,
+    /// Speed lost per millisecond of moving, the boat stops once its speed reaches 0
+    drag: u128,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            acceleration: 1,
+            max_speed: None,
+            drag: 0,
+        }
+    }
+}
+
+impl Physics {
+    /// Distance travelled in a race of `time` ms when charging for `charge_time` ms. Saturates
+    /// at u128::MAX rather than overflowing
+    fn distance(&self, time: u128, charge_time: u128) -> u128 {
+        let speed = self.acceleration.saturating_mul(charge_time);
+        let speed = self
+            .max_speed
+            .map_or(speed, |max_speed| speed.min(max_speed));
+        let remaining_time = time - charge_time;
+        if self.drag == 0 {
+            return speed.saturating_mul(remaining_time);
+        }
+        // Milliseconds spent moving, speed drops by drag after each one
+        let moving = remaining_time.min(speed.div_ceil(self.drag));
+        // moving · speed − drag · (0 + 1 + ... + moving − 1)
+        let slowdown = self
+            .drag
+            .saturating_mul(moving.saturating_mul(moving.saturating_sub(1)) / 2);
+        speed.saturating_mul(moving).saturating_sub(slowdown)
+    }
+}
+
+/// First value in low..high for which `predicate` holds, or high if there is none. The predicate
+/// has to be false up to some point and true after it
+fn first_where(mut low: u128, mut high: u128, predicate: impl Fn(u128) -> bool) -> u128 {
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if predicate(middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    low
+}
+
+impl Race {
+    /// The charge times that result in a win under any physics. Only assumes that the distance
+    /// rises to a peak and falls after it, without flat stretches below the peak, so the peak and
+    /// both ends of the winning interval can be binary searched.
+    fn winning_charge_interval_with(&self, physics: &Physics) -> Option<RangeInclusive<u128>> {
+        let distance = |charge_time| physics.distance(self.time, charge_time);
+        let peak = first_where(0, self.time, |c| distance(c + 1) <= distance(c));
+        if distance(peak) <= self.distance {
+            return None;
+        }
+        let lowest = first_where(0, peak, |c| distance(c) > self.distance);
+        let highest = first_where(peak, self.time + 1, |c| distance(c) <= self.distance) - 1;
+        Some(lowest..=highest)
+    }
+
+    fn winning_charge_count_with(&self, physics: &Physics) -> u128 {
+        self.winning_charge_interval_with(physics)
+            .map_or(0, |interval| interval.end() - interval.start() + 1)
+    }
+}
+
+/// Everything worth knowing about how a race can be won
+#[derive(Debug)]
+struct RaceAnalysis {
+    time: u128,
+    record: u128,
+    winning_charge_interval: Option<RangeInclusive<u128>>,
+    winning_charge_count: u128,
+    /// Charge time travelling the furthest. t / 2 rounded either way is equally good
+    optimal_charge_time: u128,
+    /// None if it doesn't fit in a u128
+    optimal_distance: Option<u128>,
+    /// How far the optimal distance beats the record by, None if it doesn't beat it
+    margin: Option<u128>,
+}
+
+impl RaceAnalysis {
+    fn new(race: &Race) -> Self {
+        let optimal_charge_time = race.time / 2;
+        let optimal_distance = race.calculate_distance(optimal_charge_time);
+        Self {
+            time: race.time,
+            record: race.distance,
+            winning_charge_interval: race.winning_charge_interval(),
+            winning_charge_count: race.winning_charge_count(),
+            optimal_charge_time,
+            optimal_distance,
+            margin: optimal_distance
+                .and_then(|distance| distance.checked_sub(race.distance))
+                .filter(|margin| *margin > 0),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let json_number = |n: Option<u128>| n.map_or("null".to_string(), |n| n.to_string());
+        format!(
+            "{{\"time\":{},\"record\":{},\"min_winning_charge_time\":{},\
+             \"max_winning_charge_time\":{},\"winning_charge_count\":{},\
+             \"optimal_charge_time\":{},\"optimal_distance\":{},\"margin\":{}}}",
+            self.time,
+            self.record,
+            json_number(self.winning_charge_interval.as_ref().map(|i| *i.start())),
+            json_number(self.winning_charge_interval.as_ref().map(|i| *i.end())),
+            self.winning_charge_count,
+            self.optimal_charge_time,
+            json_number(self.optimal_distance),
+            json_number(self.margin),
+        )
     }
 }
 
-fn parse_line<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Vec<usize> {
-    lines
+/// Print the analysis of every race and the combined race as a table
+fn print_table(analyses: &[RaceAnalysis], combined: &RaceAnalysis, product: u128) {
+    let cell = |n: Option<u128>| n.map_or("-".to_string(), |n| n.to_string());
+    println!(
+        "{:>10} {:>16} {:>20} {:>10} {:>10} {:>10} {:>10} {:>20} {:>16}",
+        "race", "time", "record", "min", "max", "count", "optimal", "optimal distance", "margin"
+    );
+    let labels = (1..=analyses.len()).map(|i| i.to_string());
+    for (label, analysis) in labels
+        .zip(analyses.iter())
+        .chain(std::iter::once(("combined".to_string(), combined)))
+    {
+        println!(
+            "{:>10} {:>16} {:>20} {:>10} {:>10} {:>10} {:>10} {:>20} {:>16}",
+            label,
+            analysis.time,
+            analysis.record,
+            cell(
+                analysis
+                    .winning_charge_interval
+                    .as_ref()
+                    .map(|i| *i.start())
+            ),
+            cell(analysis.winning_charge_interval.as_ref().map(|i| *i.end())),
+            analysis.winning_charge_count,
+            analysis.optimal_charge_time,
+            cell(analysis.optimal_distance),
+            cell(analysis.margin),
+        );
+    }
+    println!("Product of winning charge counts: {product}");
+}
+
+fn print_json(analyses: &[RaceAnalysis], combined: &RaceAnalysis, product: u128) {
+    let races: Vec<String> = analyses.iter().map(RaceAnalysis::to_json).collect();
+    println!(
+        "{{\"races\":[{}],\"combined\":{},\"product\":{product}}}",
+        races.join(","),
+        combined.to_json()
+    );
+}
+
+/// Concatenate the digits of all numbers into one number, eg. [7, 15, 30] => 71530. None if the
+/// result doesn't fit in a u128
+fn concat_digits(numbers: &[u128]) -> Option<u128> {
+    numbers.iter().try_fold(0_u128, |acc, &n| {
+        // 0 still has a single digit
+        let shift = 10_u128.checked_pow(n.checked_ilog10().unwrap_or(0) + 1)?;
+        acc.checked_mul(shift)?.checked_add(n)
+    })
+}
+
+/// The numbers after the colon on the next line. Fails on anything that isn't a number or doesn't
+/// fit in a u128, rather than skipping it and pairing up the wrong times and distances
+fn parse_line<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<Vec<u128>, String> {
+    let numbers_str = lines
         .next()
-        .and_then(|line| line.split(':').last())
-        .map(|numbers_str| {
-            numbers_str
-                .split_whitespace()
-                .flat_map(str::parse)
-                .collect()
+        .and_then(|line| line.split(':').next_back())
+        .ok_or("missing line")?;
+    numbers_str
+        .split_whitespace()
+        .map(|number| {
+            number
+                .parse()
+                .map_err(|error| format!("`{number}` is not a valid number: {error}"))
         })
-        .unwrap()
+        .collect()
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|arg| arg == "--json");
+    let physics = Physics {
+        acceleration: arg_value(&args, "--acceleration").unwrap_or(1),
+        max_speed: arg_value(&args, "--max-speed"),
+        drag: arg_value(&args, "--drag").unwrap_or(0),
+    };
     let mut lines = include_str!("./input").split('\n');
 
-    let times = parse_line(&mut lines);
-    let distances = parse_line(&mut lines);
+    let times = parse_line(&mut lines).unwrap_or_else(|error| panic!("Invalid times: {error}"));
+    let distances =
+        parse_line(&mut lines).unwrap_or_else(|error| panic!("Invalid distances: {error}"));
 
     let races: Vec<Race> = distances
         .iter()
         .zip(times.iter())
         .map(|(&distance, &time)| Race { time, distance })
         .collect();
-    println!("{races:?}");
 
-    let winning_charge_product: usize = races.iter().map(Race::winning_charge_count).product();
-    println!("Winning alternatives {winning_charge_product}");
+    let winning_charge_product = races
+        .iter()
+        .map(Race::winning_charge_count)
+        .try_fold(1_u128, u128::checked_mul)
+        .expect("Product of winning charge counts overflows");
 
     let combined_race = Race {
-        // Messy conversion into String and re-parsing
-        time: str::parse(&times.iter().map(usize::to_string).collect::<String>()).unwrap(),
-        distance: str::parse(&distances.iter().map(usize::to_string).collect::<String>()).unwrap(),
+        time: concat_digits(&times).expect("Combined race time overflows"),
+        distance: concat_digits(&distances).expect("Combined race distance overflows"),
     };
+
+    let analyses: Vec<RaceAnalysis> = races.iter().map(RaceAnalysis::new).collect();
+    let combined_analysis = RaceAnalysis::new(&combined_race);
+    if json {
+        print_json(&analyses, &combined_analysis, winning_charge_product);
+        return;
+    }
+
+    println!("{races:?}");
+    println!("Winning alternatives {winning_charge_product}");
     println!("{combined_race:#?}");
     println!(
         "combined_race winning_charge_count: {}",
         combined_race.winning_charge_count()
     );
+    println!(
+        "combined_race winning_charge_interval: {:?}",
+        combined_race.winning_charge_interval()
+    );
+    print_table(&analyses, &combined_analysis, winning_charge_product);
+
+    // The default physics would only repeat the table above
+    let custom_physics = ["--acceleration", "--max-speed", "--drag"]
+        .iter()
+        .any(|flag| args.iter().any(|arg| arg == flag));
+    if custom_physics {
+        println!("{physics:?}");
+        for race in races.iter().chain(std::iter::once(&combined_race)) {
+            println!(
+                "{race:?}: winning {:?}, count {}",
+                race.winning_charge_interval_with(&physics),
+                race.winning_charge_count_with(&physics)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winning_charge_count_matches_brute_force() {
+        // Distances past t² / 4 have a negative discriminant and can't be won
+        for time in 0..=40 {
+            for distance in 0..=450 {
+                let race = Race { time, distance };
+                assert_eq!(
+                    race.winning_charge_count(),
+                    race.winning_charge_count_brute_force(),
+                    "{race:?}"
+                );
+                assert_eq!(
+                    race.winning_charge_interval(),
+                    race.winning_charge_interval_with(&Physics::default()),
+                    "{race:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn negative_discriminant_never_wins() {
+        let race = Race {
+            time: 5,
+            distance: 7,
+        };
+        assert_eq!(race.winning_charge_interval(), None);
+        assert_eq!(race.winning_charge_count(), 0);
+    }
+
+    #[test]
+    fn exact_ties_do_not_win() {
+        // Charging for 2 or 5 ms travels exactly 10 mm
+        let race = Race {
+            time: 7,
+            distance: 10,
+        };
+        assert_eq!(race.winning_charge_interval(), Some(3..=4));
+        // The only charge time reaching 4 mm ties the record
+        let race = Race {
+            time: 4,
+            distance: 4,
+        };
+        assert_eq!(race.winning_charge_interval(), None);
+    }
 }
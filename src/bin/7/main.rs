@@ -1,28 +1,337 @@
-use std::{
-    cmp::Ordering,
-    collections::{hash_map::Entry, HashMap},
-};
+use advent_of_code::arg_value;
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
 
 #[derive(Clone, Debug)]
 struct Card {
     face: char,
-    /// Jokers are imposters with another face
-    joker: bool,
+    /// The face a wild card pretends to be once resolved
+    stands_for: Option<char>,
 }
+
 impl Card {
-    fn to_value(&self, face_value: bool) -> usize {
-        if self.joker && !face_value {
-            // Jokers are the weakest and we ignore their fake face
-            1
+    /// The face this card counts as when classifying the hand
+    fn effective_face(&self) -> char {
+        self.stands_for.unwrap_or(self.face)
+    }
+}
+
+/// A card is shown as its face, followed by the face it stands for in brackets if it is a
+/// resolved wild card, eg. `J(Q)`
+impl std::fmt::Display for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.stands_for {
+            Some(stands_for) => write!(f, "{}({stands_for})", self.face),
+            None => write!(f, "{}", self.face),
+        }
+    }
+}
+
+impl FromStr for Card {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut chars = text.chars();
+        let face = chars.next().ok_or("Empty card")?;
+        match (chars.next(), chars.next(), chars.next(), chars.next()) {
+            (None, ..) => Ok(Card {
+                face,
+                stands_for: None,
+            }),
+            (Some('('), Some(stands_for), Some(')'), None) => Ok(Card {
+                face,
+                stands_for: Some(stands_for),
+            }),
+            _ => Err(format!("Invalid card `{text}`")),
+        }
+    }
+}
+
+/// Cards as listed in a hand, before any rules give them meaning, eg. `KTJ(T)J(T)T`
+struct Cards(Vec<Card>);
+
+impl std::fmt::Display for Cards {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|card| write!(f, "{card}"))
+    }
+}
+
+impl FromStr for Cards {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // Split into one piece per card, a card runs until the next face
+        let mut pieces: Vec<&str> = vec![];
+        let mut start = 0;
+        let mut in_brackets = false;
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => in_brackets = true,
+                ')' => in_brackets = false,
+                _ if !in_brackets && i > start => {
+                    pieces.push(&text[start..i]);
+                    start = i;
+                }
+                _ => {}
+            }
+        }
+        pieces.push(&text[start..]);
+        pieces
+            .into_iter()
+            .map(str::parse)
+            .collect::<Result<Vec<Card>, _>>()
+            .map(Cards)
+    }
+}
+
+/// The type of a hand is how many cards it has of each face, largest group first, eg. [3, 2] for
+/// a full house. Comparing these largest group first gives the puzzle's order of hand types for
+/// hands of any size.
+#[derive(Clone, Debug)]
+struct HandType {
+    frequencies: Vec<usize>,
+    /// Name given by a table of hand types, otherwise the name is generated when shown
+    name: Option<String>,
+}
+
+impl HandType {
+    fn new(mut frequencies: Vec<usize>) -> Self {
+        frequencies.sort_by(|a, b| b.cmp(a));
+        Self {
+            frequencies,
+            name: None,
+        }
+    }
+
+    /// Name a hand type, eg. "full house", "two triples" or "four pair"
+    fn describe(frequencies: &[usize]) -> String {
+        let number = |n: usize| match n {
+            1 => "one".to_string(),
+            2 => "two".to_string(),
+            3 => "three".to_string(),
+            4 => "four".to_string(),
+            5 => "five".to_string(),
+            6 => "six".to_string(),
+            7 => "seven".to_string(),
+            8 => "eight".to_string(),
+            9 => "nine".to_string(),
+            _ => n.to_string(),
+        };
+        if frequencies == [3, 2] {
+            return "full house".to_string();
+        }
+        // Describe each group size of two or more, with how many groups there are of it
+        let mut groups: Vec<String> = vec![];
+        for size in frequencies.iter().copied().filter(|size| *size > 1) {
+            let count = frequencies.iter().filter(|f| **f == size).count();
+            let group = match (size, count) {
+                (2, count) => format!("{} pair", number(count)),
+                (3, 1) => "three of a kind".to_string(),
+                (3, count) => format!("{} triples", number(count)),
+                (size, 1) => format!("{} of a kind", number(size)),
+                (size, count) => format!("{} times {} of a kind", number(count), number(size)),
+            };
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        if groups.is_empty() {
+            "high card".to_string()
+        } else {
+            groups.join(" and ")
+        }
+    }
+}
+
+impl std::fmt::Display for HandType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "{}", HandType::describe(&self.frequencies)),
+        }
+    }
+}
+
+/// Hand types are the same if they have the same frequencies, the name is only for showing them
+impl std::cmp::Eq for HandType {}
+impl std::cmp::PartialEq for HandType {
+    fn eq(&self, other: &Self) -> bool {
+        self.frequencies == other.frequencies
+    }
+}
+impl std::cmp::PartialOrd for HandType {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl std::cmp::Ord for HandType {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.frequencies.cmp(&other.frequencies)
+    }
+}
+
+/// The rules hands are scored under
+#[derive(Clone, Debug)]
+struct Rules {
+    /// Faces from weakest to strongest
+    card_order: Vec<char>,
+    /// Faces that can pretend to be any other face
+    wild: Vec<char>,
+    /// Whether wild cards are weaker than any other card when comparing cards one by one.
+    /// Otherwise they keep the strength of their own face
+    wild_lowest: bool,
+    /// Hand types strongest first, if they don't simply rank by their frequencies. Hands
+    /// matching none of them rank as the weakest type
+    hand_types: Option<Vec<HandType>>,
+}
+
+impl Rules {
+    fn new(card_order: &str, wild: &str, wild_lowest: bool) -> Self {
+        Self {
+            card_order: card_order.chars().collect(),
+            wild: wild.chars().collect(),
+            wild_lowest,
+            hand_types: None,
+        }
+    }
+
+    fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "part-one" => Some(Rules::new("23456789TJQKA", "", false)),
+            "part-two" => Some(Rules::new("23456789TJQKA", "J", true)),
+            // House rules: deuces are wild but still the weakest card
+            "deuces-wild" => Some(Rules::new("23456789TJQKA", "2", true)),
+            // House rules: jokers are wild but keep their strength when comparing cards
+            "strong-jokers" => Some(Rules::new("23456789TJQKA", "J", false)),
+            _ => None,
+        }
+    }
+
+    /// Parse rules from lines of `key: value`, eg.
+    ///
+    /// ```text
+    /// order: 23456789TJQKA
+    /// wild: J
+    /// wild lowest: true
+    /// type: five of a kind = 5
+    /// type: four of a kind = 4 1
+    /// ```
+    ///
+    /// Hand types are listed strongest first, named as they should be shown. If none are given,
+    /// hand types rank by their frequencies, largest group first.
+    /// Empty lines and lines starting with `#` are ignored.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = Rules::new("", "", false);
+        let mut hand_types = vec![];
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or(format!("Expected `key: value`, got `{line}`"))?;
+            match key {
+                "order" => rules.card_order = value.chars().collect(),
+                "wild" => rules.wild = value.chars().collect(),
+                "wild lowest" => {
+                    rules.wild_lowest = value
+                        .parse()
+                        .map_err(|_| format!("Expected true or false, got `{value}`"))?
+                }
+                "type" => {
+                    let (name, frequencies) = value
+                        .split_once('=')
+                        .ok_or(format!("Expected `name = frequencies`, got `{value}`"))?;
+                    let frequencies = frequencies
+                        .split_whitespace()
+                        .map(|n| n.parse().map_err(|_| format!("Invalid frequency `{n}`")))
+                        .collect::<Result<Vec<usize>, _>>()?;
+                    let mut hand_type = HandType::new(frequencies);
+                    hand_type.name = Some(name.trim().to_string());
+                    hand_types.push(hand_type);
+                }
+                _ => return Err(format!("Unknown key `{key}`")),
+            }
+        }
+        if rules.card_order.is_empty() {
+            return Err("Card order needs at least one face".to_string());
+        }
+        if let Some(face) = rules
+            .wild
+            .iter()
+            .find(|face| !rules.card_order.contains(face))
+        {
+            return Err(format!("Wild face {face} is not in the card order"));
+        }
+        if !hand_types.is_empty() {
+            rules.hand_types = Some(hand_types);
+        }
+        Ok(rules)
+    }
+
+    /// Rules by name, or else read from the file at that path
+    fn load(name_or_path: &str) -> Result<Self, String> {
+        match Rules::by_name(name_or_path) {
+            Some(rules) => Ok(rules),
+            None => std::fs::read_to_string(name_or_path)
+                .map_err(|error| format!("Can't read rules {name_or_path}: {error}"))
+                .and_then(|text| Rules::parse(&text)),
+        }
+    }
+
+    fn is_wild(&self, card: &Card) -> bool {
+        self.wild.contains(&card.face)
+    }
+
+    fn face_index(&self, face: char) -> usize {
+        self.card_order
+            .iter()
+            .position(|f| *f == face)
+            .unwrap_or_else(|| panic!("Unknown face {face}"))
+    }
+
+    /// Strength of a card when comparing hands of the same type card by card
+    fn card_value(&self, card: &Card) -> usize {
+        if self.wild_lowest && self.is_wild(card) {
+            0
         } else {
-            match self.face {
-                'A' => 14,
-                'K' => 13,
-                'Q' => 12,
-                'J' => 11,
-                'T' => 10,
-                '2'..='9' => self.face.to_digit(10).unwrap() as usize,
-                _ => panic!(),
+            self.face_index(card.face) + 1
+        }
+    }
+
+    fn strongest_face(&self) -> char {
+        *self.card_order.last().unwrap()
+    }
+
+    /// The type of a hand and its strength, for comparing against other hands
+    fn classify(&self, cards: &[Card]) -> (HandType, Vec<usize>) {
+        // How many of each face we have, wild cards count as the face they pretend to be
+        let mut counts = vec![0_usize; self.card_order.len()];
+        cards
+            .iter()
+            .for_each(|card| counts[self.face_index(card.effective_face())] += 1);
+        let hand_type = HandType::new(counts.into_iter().filter(|n| *n > 0).collect());
+        match &self.hand_types {
+            Some(hand_types) => {
+                let weakest = hand_types.len() - 1;
+                match hand_types.iter().position(|listed| *listed == hand_type) {
+                    Some(index) => (hand_types[index].clone(), vec![weakest - index]),
+                    // Ranks with the weakest type, but keeps its own frequencies and says so
+                    None => {
+                        let name = format!("unlisted ({hand_type})");
+                        let hand_type = HandType {
+                            name: Some(name),
+                            ..hand_type
+                        };
+                        (hand_type, vec![0])
+                    }
+                }
+            }
+            None => {
+                // Pad to the number of cards so the card values that follow line up
+                let mut strength = hand_type.frequencies.clone();
+                strength.resize(cards.len(), 0);
+                (hand_type, strength)
             }
         }
     }
@@ -30,132 +339,174 @@ impl Card {
 
 #[derive(Clone, Debug)]
 struct Hand {
-    cards: [Card; 5],
+    cards: Vec<Card>,
+    hand_type: HandType,
+    /// Strength of the hand type followed by the value of each card, so that hands sort by
+    /// comparing integers
+    sort_key: Vec<usize>,
 }
 
 impl Hand {
-    fn new(line: &str) -> (Self, usize) {
-        let (cards, bid) = line.split_once(' ').unwrap();
-        (
-            Self {
-                cards: cards
-                    .chars()
-                    .map(|value| Card {
-                        face: value,
-                        joker: false,
-                    })
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap(),
-            },
-            bid.parse().unwrap(),
-        )
-    }
-
-    /// Find the best hand that we can replace the joker with
-    fn resolve_joker(self) -> Self {
+    fn from_cards(cards: Vec<Card>, rules: &Rules) -> Self {
+        let (hand_type, mut sort_key) = rules.classify(&cards);
+        sort_key.extend(cards.iter().map(|card| rules.card_value(card)));
+        Self {
+            cards,
+            hand_type,
+            sort_key,
+        }
+    }
+
+    /// A hand and its bid from a line of input, eg. `KTJJT 220`
+    fn new(line: &str, rules: &Rules) -> Result<(Self, usize), String> {
+        let (cards, bid) = line
+            .split_once(' ')
+            .ok_or(format!("Expected a hand and a bid, got `{line}`"))?;
+        let hand = Self::parse(cards, rules)?;
+        let bid = bid
+            .parse()
+            .map_err(|_| format!("Invalid bid `{bid}` in `{line}`"))?;
+        Ok((hand, bid))
+    }
+
+    /// Parse a hand as shown by its Display, eg. `KTJ(T)J(T)T`, checking its cards against the
+    /// rules. Plain faces are fine too
+    fn parse(text: &str, rules: &Rules) -> Result<Self, String> {
+        let Cards(cards) = text.parse()?;
+        for card in cards.iter() {
+            if !rules.card_order.contains(&card.face)
+                || !rules.card_order.contains(&card.effective_face())
+            {
+                return Err(format!("Unknown face in {card}"));
+            }
+            if card.stands_for.is_some() && !rules.is_wild(card) {
+                return Err(format!(
+                    "{card} isn't wild, so it can't stand for another face"
+                ));
+            }
+        }
+        Ok(Self::from_cards(cards, rules))
+    }
+
+    /// The faces of the cards as listed
+    fn faces(&self) -> String {
+        self.cards.iter().map(|card| card.face).collect()
+    }
+
+    /// Find the best hand that the wild cards can make
+    fn resolve_wild(self, rules: &Rules) -> Self {
+        if rules.hand_types.is_none() {
+            self.resolve_joker(rules)
+        } else {
+            // Joining the largest group isn't necessarily best for other hand types
+            self.resolve_joker_brute_force(rules)
+        }
+    }
+
+    /// Find the best hand that we can replace the joker with. Jokers are best used by joining the
+    /// largest group of other cards, and which face they pretend to be doesn't matter when
+    /// comparing cards. A hand of only jokers makes them all the strongest face.
+    fn resolve_joker(self, rules: &Rules) -> Self {
+        let mut counts = vec![0_usize; rules.card_order.len()];
+        self.cards
+            .iter()
+            .filter(|card| !rules.is_wild(card))
+            .for_each(|card| counts[rules.face_index(card.face)] += 1);
+        // Strongest face among the most frequent, so the result doesn't depend on card order
+        let best_face = self
+            .cards
+            .iter()
+            .filter(|card| !rules.is_wild(card))
+            .max_by_key(|card| {
+                let index = rules.face_index(card.face);
+                (counts[index], index)
+            })
+            .map_or(rules.strongest_face(), |card| card.face);
+        let cards = self
+            .cards
+            .into_iter()
+            .map(|card| {
+                if rules.is_wild(&card) {
+                    Card {
+                        face: card.face,
+                        stands_for: Some(best_face),
+                    }
+                } else {
+                    card
+                }
+            })
+            .collect();
+        Hand::from_cards(cards, rules)
+    }
+
+    /// Find the best hand that we can replace the joker with by trying every replacement. Slow,
+    /// but kept as a reference to check resolve_joker against
+    fn resolve_joker_brute_force(self, rules: &Rules) -> Self {
         let joker_positions: Vec<_> = self
             .cards
             .iter()
             .enumerate()
-            .filter_map(|(i, card)| if card.face == 'J' { Some(i) } else { None })
+            .filter_map(|(i, card)| rules.is_wild(card).then_some(i))
             .collect();
         if joker_positions.is_empty() {
             self
         } else {
-            // The alternatives that we can replace the joker with
-            let mut replacements: Vec<_> = self
-                .cards
-                .iter()
-                .filter(|card| card.face != 'J')
-                .cloned()
-                .collect();
+            // The faces that we can replace the joker with. Joining a face already in the hand or
+            // being the strongest face is enough for hand types ranked by their frequencies, but
+            // with a table of hand types any face might be best
+            let mut replacements: Vec<char> = if rules.hand_types.is_some() {
+                rules.card_order.clone()
+            } else {
+                self.cards
+                    .iter()
+                    .filter(|card| !rules.is_wild(card))
+                    .map(|card| card.face)
+                    .chain(std::iter::once(rules.strongest_face()))
+                    .collect()
+            };
+            replacements.sort_by_key(|face| rules.face_index(*face));
+            replacements.dedup();
 
-            // Mark all replacment cards as jokers
-            replacements.iter_mut().for_each(|card| card.joker = true);
-
-            // What if the joker can be 'A'. Is redundant if replacements already contains 'A'
-            replacements.push(Card {
-                face: 'A',
-                joker: true,
-            });
-
-            // Generate all hands with the joker replaced
-            // For each joker_position, replace it with all possibilities
-            let mut hands: Vec<Hand> =
-                joker_positions
-                    .into_iter()
-                    .fold(vec![self], |hands, joker_position| {
-                        hands
-                            .into_iter()
-                            .flat_map(|hand| {
-                                replacements
-                                    .iter()
-                                    .map(|card| {
-                                        let mut new_hand = hand.clone();
-                                        new_hand.cards[joker_position] = card.clone();
-                                        new_hand
-                                    })
-                                    .collect::<Vec<_>>()
-                            })
-                            .collect()
-                    });
+            // Generate all hands with the joker replaced. Which joker stands for which face
+            // doesn't change the hand, so each joker only takes faces from where the previous
+            // one left off
+            let mut hands: Vec<Hand> = joker_positions
+                .into_iter()
+                .fold(vec![(self, 0)], |hands, joker_position| {
+                    hands
+                        .into_iter()
+                        .flat_map(|(hand, first)| {
+                            (first..replacements.len())
+                                .map(|i| {
+                                    let mut cards = hand.cards.clone();
+                                    cards[joker_position].stands_for = Some(replacements[i]);
+                                    (Hand::from_cards(cards, rules), i)
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                        .collect()
+                })
+                .into_iter()
+                .map(|(hand, _)| hand)
+                .collect();
 
             // Sort and return the best hand
             hands.sort();
             hands.last().unwrap().clone()
         }
     }
+}
 
-    /// Get the rank of a hand
-    /// five of a kind => 6
-    /// four of a kind => 5
-    /// full house => 4
-    /// three of a kind => 3
-    /// two pair => 2
-    /// one pair => 1
-    /// high card => 0
-    fn hand_rank(&self) -> usize {
-        let frequencies: HashMap<usize, usize> =
-            self.cards.iter().fold(HashMap::new(), |mut acc, card| {
-                match acc.entry(card.to_value(true)) {
-                    Entry::Occupied(mut o) => {
-                        *o.get_mut() += 1;
-                    }
-                    Entry::Vacant(v) => {
-                        v.insert(1);
-                    }
-                };
-                acc
-            });
-        let frequencies: Vec<_> = frequencies.values().cloned().collect();
-        let max_of_a_kind = *frequencies.iter().max().unwrap();
-        if max_of_a_kind == 1 {
-            // High card
-            0
-        } else if max_of_a_kind == 2 {
-            // Either one or two pairs
-            frequencies.iter().filter(|n| **n == 2).count()
-        } else if max_of_a_kind == 3 {
-            // Do we have a house?
-            if frequencies.iter().any(|v| *v == 2) {
-                4
-            } else {
-                3
-            }
-        } else {
-            max_of_a_kind + 1
-        }
+impl std::fmt::Display for Hand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.cards.iter().try_for_each(|card| write!(f, "{card}"))
     }
 }
 
 impl std::cmp::Eq for Hand {}
 impl std::cmp::PartialEq for Hand {
     fn eq(&self, other: &Self) -> bool {
-        match self.cmp(other) {
-            Ordering::Equal => true,
-            Ordering::Greater | Ordering::Less => false,
-        }
+        self.sort_key == other.sort_key
     }
 }
 impl std::cmp::PartialOrd for Hand {
@@ -165,61 +516,482 @@ impl std::cmp::PartialOrd for Hand {
 }
 impl std::cmp::Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
-        let ordering = self.hand_rank().cmp(&other.hand_rank());
-        match ordering {
-            Ordering::Equal => {
-                // If hand_rank is equal, then we need to compare each card
-                let zipped_cards = self.cards.iter().zip(other.cards.iter());
-                for next_pair in zipped_cards {
-                    // When comparing each card, need to treat the joker as 1, so face_value ==
-                    // false
-                    let card_ordering = next_pair
-                        .0
-                        .to_value(false)
-                        .cmp(&next_pair.1.to_value(false));
-                    match card_ordering {
-                        Ordering::Equal => continue,
-                        Ordering::Greater | Ordering::Less => return card_ordering,
-                    }
+        self.sort_key.cmp(&other.sort_key)
+    }
+}
+
+/// Print how many hands there are of each type
+fn print_hand_types(hands: &[(Hand, usize)]) {
+    let mut counts: BTreeMap<&HandType, usize> = BTreeMap::new();
+    hands
+        .iter()
+        .for_each(|(hand, _)| *counts.entry(&hand.hand_type).or_default() += 1);
+    for (hand_type, count) in counts.iter().rev() {
+        println!("{hand_type}: {count}");
+    }
+}
+
+/// How hands that compare equal are ranked
+#[derive(Clone, Copy, Debug)]
+enum TiePolicy {
+    /// Tied hands are ranked in the order they were listed in
+    InputOrder,
+    /// Tied hands share the average of the ranks they take up
+    SharedRank,
+    /// Any tie is an error
+    Error,
+}
+
+impl FromStr for TiePolicy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "input-order" => Ok(TiePolicy::InputOrder),
+            "shared-rank" => Ok(TiePolicy::SharedRank),
+            "error" => Ok(TiePolicy::Error),
+            _ => Err(format!("Unknown tie policy {name}")),
+        }
+    }
+}
+
+/// Hands ranked under some rules
+struct Ranking {
+    /// Hands with their bids, weakest first
+    hands: Vec<(Hand, usize)>,
+    /// Rank of each hand times two, so that shared ranks (averages) stay whole numbers
+    double_ranks: Vec<usize>,
+    /// Positions of hands that compare equal to each other
+    ties: Vec<std::ops::Range<usize>>,
+}
+
+/// Show a number given in halves, eg. 5 => 2.5
+fn halves(double: usize) -> String {
+    if double.is_multiple_of(2) {
+        (double / 2).to_string()
+    } else {
+        format!("{}.5", double / 2)
+    }
+}
+
+impl Ranking {
+    fn total_winnings(&self) -> String {
+        let double_sum: usize = self
+            .hands
+            .iter()
+            .zip(self.double_ranks.iter())
+            .map(|((_, bid), double_rank)| double_rank * bid)
+            .sum();
+        halves(double_sum)
+    }
+
+    /// Print every hand, strongest first, with its type, rank, bid and winnings
+    fn print_listing(&self) {
+        println!(
+            "{:>20} {:>36} {:>8} {:>8} {:>12}",
+            "hand", "type", "rank", "bid", "winnings"
+        );
+        for ((hand, bid), double_rank) in self.hands.iter().zip(self.double_ranks.iter()).rev() {
+            println!(
+                "{:>20} {:>36} {:>8} {:>8} {:>12}",
+                hand.to_string(),
+                hand.hand_type.to_string(),
+                halves(*double_rank),
+                bid,
+                halves(double_rank * bid)
+            );
+        }
+    }
+
+    fn print_ties(&self) {
+        for tie in self.ties.iter() {
+            let hands: Vec<String> = self.hands[tie.clone()]
+                .iter()
+                .map(|(hand, bid)| format!("{} (bid {bid})", hand.faces()))
+                .collect();
+            println!(
+                "Tie at ranks {}-{}: {}",
+                tie.start + 1,
+                tie.end,
+                hands.join(", ")
+            );
+        }
+    }
+}
+
+/// Print every hand that is listed more than once, with the lines it is on
+fn print_duplicates(lines: &[&str]) {
+    let mut seen: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, line) in lines.iter().enumerate() {
+        // Malformed lines are reported when scoring
+        let cards = line.split_once(' ').map_or(*line, |(cards, _)| cards);
+        seen.entry(cards).or_default().push(i + 1);
+    }
+    for (cards, line_numbers) in seen.iter().filter(|(_, lines)| lines.len() > 1) {
+        println!("Duplicate hand {cards} on lines {line_numbers:?}");
+    }
+}
+
+/// Rank the hands under the given rules, ranking hands that compare equal by the tie policy
+fn score(lines: &[&str], rules: &Rules, tie_policy: TiePolicy) -> Result<Ranking, String> {
+    // Put hand in a tuple alongside bid (so we can keep track of the corresponding bid to a hand)
+    let mut hands: Vec<(Hand, usize)> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let (hand, bid) =
+                Hand::new(line, rules).map_err(|error| format!("Line {}: {error}", i + 1))?;
+            if rules.wild.is_empty() {
+                Ok((hand, bid))
+            } else {
+                Ok((hand.resolve_wild(rules), bid))
+            }
+        })
+        .collect::<Result<_, String>>()?;
+
+    // Only sort by hand, the sort is stable so ties stay in input order
+    hands.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // Group hands that compare equal
+    let mut groups: Vec<std::ops::Range<usize>> = vec![];
+    for i in 0..hands.len() {
+        match groups.last_mut() {
+            Some(group) if hands[group.start].0 == hands[i].0 => group.end = i + 1,
+            _ => groups.push(i..(i + 1)),
+        }
+    }
+    let ties: Vec<_> = groups
+        .iter()
+        .filter(|group| group.len() > 1)
+        .cloned()
+        .collect();
+
+    let double_ranks = match tie_policy {
+        TiePolicy::Error if !ties.is_empty() => {
+            let tied: Vec<String> = ties
+                .iter()
+                .map(|tie| {
+                    let faces: Vec<String> = hands[tie.clone()]
+                        .iter()
+                        .map(|(hand, _)| hand.faces())
+                        .collect();
+                    faces.join(" = ")
+                })
+                .collect();
+            return Err(format!("Tied hands: {}", tied.join(", ")));
+        }
+        TiePolicy::InputOrder | TiePolicy::Error => {
+            (1..=hands.len()).map(|rank| rank * 2).collect()
+        }
+        // The average of ranks start + 1 to end is (start + 1 + end) / 2
+        TiePolicy::SharedRank => groups
+            .iter()
+            .flat_map(|group| vec![group.start + 1 + group.end; group.len()])
+            .collect(),
+    };
+    Ok(Ranking {
+        hands,
+        double_ranks,
+        ties,
+    })
+}
+
+/// A deck to draw random hands from, with a number of copies of every face
+struct Deck {
+    rules: Rules,
+    faces: Vec<(char, usize)>,
+}
+
+impl Deck {
+    /// `copies` of every face in the rules' card order, plus `jokers` extra wild cards shown as
+    /// `*` that are weaker than any other card
+    fn new(rules: &Rules, copies: usize, jokers: usize) -> Self {
+        let mut rules = rules.clone();
+        let mut faces: Vec<(char, usize)> = rules
+            .card_order
+            .iter()
+            .map(|face| (*face, copies))
+            .collect();
+        if jokers > 0 {
+            rules.card_order.insert(0, '*');
+            rules.wild.push('*');
+            faces.push(('*', jokers));
+        }
+        Self { rules, faces }
+    }
+
+    fn size(&self) -> usize {
+        self.faces.iter().map(|(_, copies)| copies).sum()
+    }
+
+    fn hand_type(&self, cards: Vec<Card>) -> HandType {
+        Hand::from_cards(cards, &self.rules)
+            .resolve_wild(&self.rules)
+            .hand_type
+    }
+
+    /// Exact number of hands of each type. Rather than every hand, this goes through how many
+    /// of each face a hand can hold, each of which stands for the product of C(copies, count)
+    /// hands
+    fn hand_type_counts(&self, hand_size: usize) -> BTreeMap<HandType, u128> {
+        fn binomial(n: usize, k: usize) -> u128 {
+            (0..k).fold(1, |acc, i| acc * (n - i) as u128 / (i + 1) as u128)
+        }
+
+        fn enumerate(
+            deck: &Deck,
+            face_index: usize,
+            remaining: usize,
+            cards: &mut Vec<Card>,
+            ways: u128,
+            counts: &mut BTreeMap<HandType, u128>,
+        ) {
+            let Some(&(face, copies)) = deck.faces.get(face_index) else {
+                if remaining == 0 {
+                    *counts.entry(deck.hand_type(cards.clone())).or_default() += ways;
                 }
-                // If all cards are equal then we are truly equal
-                Ordering::Equal
+                return;
+            };
+            for count in 0..=copies.min(remaining) {
+                cards.extend((0..count).map(|_| Card {
+                    face,
+                    stands_for: None,
+                }));
+                let ways = ways * binomial(copies, count);
+                enumerate(deck, face_index + 1, remaining - count, cards, ways, counts);
+                cards.truncate(cards.len() - count);
             }
-            Ordering::Greater | Ordering::Less => ordering,
         }
+
+        let mut counts = BTreeMap::new();
+        enumerate(self, 0, hand_size, &mut vec![], 1, &mut counts);
+        counts
     }
+
+    /// Number of hands of each type in `samples` random hands
+    fn sample_hand_types(
+        &self,
+        hand_size: usize,
+        samples: usize,
+        seed: u64,
+    ) -> BTreeMap<HandType, usize> {
+        let mut cards: Vec<Card> = self
+            .faces
+            .iter()
+            .flat_map(|&(face, copies)| {
+                (0..copies).map(move |_| Card {
+                    face,
+                    stands_for: None,
+                })
+            })
+            .collect();
+        // xorshift, good enough for sampling and keeps runs reproducible
+        let mut state = seed.max(1);
+        let mut random = |below: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % below as u64) as usize
+        };
+        let mut counts = BTreeMap::new();
+        for _ in 0..samples {
+            // Partial Fisher-Yates shuffle, the first hand_size cards are the hand
+            for i in 0..hand_size {
+                let j = i + random(cards.len() - i);
+                cards.swap(i, j);
+            }
+            *counts
+                .entry(self.hand_type(cards[..hand_size].to_vec()))
+                .or_default() += 1;
+        }
+        counts
+    }
+}
+
+/// Print the exact probability of every hand type, next to the share of random samples
+fn print_probabilities(
+    deck: &Deck,
+    hand_size: usize,
+    samples: usize,
+    seed: u64,
+) -> Result<(), String> {
+    if hand_size > deck.size() {
+        return Err(format!(
+            "Hands of {hand_size} don't fit in a deck of {} cards",
+            deck.size()
+        ));
+    }
+    let exact = deck.hand_type_counts(hand_size);
+    let sampled = deck.sample_hand_types(hand_size, samples, seed);
+    let total: u128 = exact.values().sum();
+    println!(
+        "{} cards, hands of {hand_size}, wild {:?}: {total} hands",
+        deck.size(),
+        deck.rules.wild
+    );
+    println!(
+        "{:>36} {:>16} {:>12} {:>12}",
+        "type", "hands", "exact", "sampled"
+    );
+    for (hand_type, count) in exact.iter().rev() {
+        let sampled = sampled.get(hand_type).copied().unwrap_or(0);
+        println!(
+            "{:>36} {:>16} {:>11.6}% {:>11.6}%",
+            hand_type.to_string(),
+            count,
+            *count as f64 / total as f64 * 100.0,
+            sampled as f64 / samples as f64 * 100.0
+        );
+    }
+    Ok(())
 }
 
 fn main() {
-    let lines = include_str!("./input")
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let lines: Vec<&str> = include_str!("./input")
         .split('\n')
-        .filter(|line| !line.is_empty());
+        .filter(|line| !line.is_empty())
+        .collect();
 
-    // Put hand in a tuple alongside bid (so we can keep track of the corresponding bid to a hand)
-    let mut hands: Vec<(Hand, usize)> = lines.map(Hand::new).collect();
+    // Part one and two, or any other rules by name or from a file with `--rules`
+    let rule_sets = match arg_value::<String>(&args, "--rules") {
+        Some(name_or_path) => {
+            vec![Rules::load(&name_or_path).unwrap_or_else(|error| panic!("{error}"))]
+        }
+        None => vec![
+            Rules::by_name("part-one").unwrap(),
+            Rules::by_name("part-two").unwrap(),
+        ],
+    };
 
-    // Simply sorting the tuple seems to work as the input doesnt have two equal hands with different
-    // bids, lmao
-    hands.sort();
+    // Hand type probabilities for random hands instead, eg. `--probabilities --jokers 2`
+    if args.iter().any(|arg| arg == "--probabilities") {
+        let number = |flag: &str, default: usize| arg_value(&args, flag).unwrap_or(default);
+        for rules in rule_sets.iter() {
+            let deck = Deck::new(rules, number("--copies", 4), number("--jokers", 0));
+            print_probabilities(
+                &deck,
+                number("--hand-size", 5),
+                number("--samples", 100_000),
+                number("--seed", 1) as u64,
+            )
+            .unwrap_or_else(|error| panic!("{error}"));
+        }
+        return;
+    }
 
-    let sum: usize = hands
-        .iter()
-        .enumerate()
-        .map(|(i, (_, bid))| (i + 1) * bid)
-        .sum();
-    println!("{sum}");
-
-    // Part two
-    let mut hands_joker_resolved: Vec<_> = hands
-        .into_iter()
-        .map(|(hand, bid)| (hand.resolve_joker(), bid))
-        .collect();
-    hands_joker_resolved.sort();
+    let tie_policy: TiePolicy = arg_value(&args, "--ties").unwrap_or(TiePolicy::InputOrder);
+    print_duplicates(&lines);
 
-    let sum: usize = hands_joker_resolved
-        .iter()
-        .enumerate()
-        .map(|(i, (_, bid))| (i + 1) * bid)
-        .sum();
-    println!("{sum}");
+    for rules in rule_sets.iter() {
+        let ranking = score(&lines, rules, tie_policy).unwrap_or_else(|error| panic!("{error}"));
+        if args.iter().any(|arg| arg == "--list") {
+            ranking.print_listing();
+        }
+        print_hand_types(&ranking.hands);
+        ranking.print_ties();
+        println!("{}", ranking.total_winnings());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every hand of `size` cards using only the given faces
+    fn all_hands(faces: &str, size: usize) -> Vec<String> {
+        (0..size).fold(vec![String::new()], |hands, _| {
+            hands
+                .iter()
+                .flat_map(|hand| faces.chars().map(move |face| format!("{hand}{face}")))
+                .collect()
+        })
+    }
+
+    fn assert_resolve_joker_matches_brute_force(hand: &str, rules: &Rules) {
+        let hand = Hand::parse(hand, rules).unwrap();
+        assert_eq!(
+            hand.clone().resolve_joker(rules),
+            hand.clone().resolve_joker_brute_force(rules),
+            "{hand}"
+        );
+    }
+
+    #[test]
+    fn resolve_joker_all_jokers() {
+        for name in ["part-two", "deuces-wild", "strong-jokers"] {
+            let rules = Rules::by_name(name).unwrap();
+            let wild = rules.wild[0];
+            for size in 1..=6 {
+                let hand: String = std::iter::repeat_n(wild, size).collect();
+                assert_resolve_joker_matches_brute_force(&hand, &rules);
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_joker_mixed_hands() {
+        for name in ["part-two", "deuces-wild", "strong-jokers"] {
+            let rules = Rules::by_name(name).unwrap();
+            let faces: String = rules
+                .wild
+                .iter()
+                .chain(['3', 'Q', 'K', 'A'].iter())
+                .collect();
+            for hand in all_hands(&faces, 5) {
+                assert_resolve_joker_matches_brute_force(&hand, &rules);
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_wild_with_hand_types_splits_wild_cards() {
+        // Five of a kind is the weakest hand in the house rules, four aces and a king are better
+        let rules = Rules::parse(include_str!("./house-rules")).unwrap();
+        let hand = Hand::parse("22222", &rules).unwrap().resolve_wild(&rules);
+        assert_eq!(hand.hand_type.to_string(), "four of a kind");
+    }
+
+    #[test]
+    fn unlisted_hand_types_keep_their_shape() {
+        // Five of a kind isn't in the house rules' table
+        let rules = Rules::parse(include_str!("./house-rules")).unwrap();
+        let five_of_a_kind = Hand::parse("JJJJJ", &rules).unwrap().resolve_wild(&rules);
+        assert_eq!(
+            five_of_a_kind.hand_type.to_string(),
+            "unlisted (five of a kind)"
+        );
+        assert_eq!(five_of_a_kind.hand_type.frequencies, [5]);
+        // It still ranks with the weakest listed type, so the cards decide
+        let high_card = Hand::parse("KQT93", &rules).unwrap();
+        assert_eq!(high_card.hand_type.to_string(), "high card");
+        assert!(five_of_a_kind < high_card);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_cards() {
+        let rules = Rules::by_name("part-two").unwrap();
+        assert!(Hand::parse("X(A)KKKK", &rules).is_err());
+        assert!(Hand::parse("J(X)KKKK", &rules).is_err());
+        assert!(Hand::parse("A(K)KKKK", &rules).is_err());
+        assert!(Hand::parse("J(K)KKKK", &rules).is_ok());
+    }
+
+    #[test]
+    fn display_parse_round_trip() {
+        for name in ["part-one", "part-two", "deuces-wild", "strong-jokers"] {
+            let rules = Rules::by_name(name).unwrap();
+            for line in include_str!("./input")
+                .lines()
+                .filter(|line| !line.is_empty())
+            {
+                let hand = Hand::new(line, &rules).unwrap().0.resolve_wild(&rules);
+                let cards: Cards = hand.to_string().parse().unwrap();
+                assert_eq!(cards.to_string(), hand.to_string());
+                let parsed = Hand::from_cards(cards.0, &rules);
+                assert_eq!(parsed.to_string(), hand.to_string());
+                assert_eq!(parsed, hand);
+            }
+        }
+    }
 }
@@ -1,9 +1,248 @@
+use advent_of_code::arg_value;
+use std::{cmp::Ordering, fmt};
+
+/// Just enough of an arbitrary precision integer to extrapolate far beyond the given terms,
+/// where values quickly outgrow even an i128
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BigInt {
+    negative: bool,
+    /// Base 2^32 digits, least significant first, without trailing zeros
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        // Zero is never negative
+        let negative = negative && !magnitude.is_empty();
+        Self {
+            negative,
+            magnitude,
+        }
+    }
+
+    fn from_i64(n: i64) -> Self {
+        let value = n.unsigned_abs();
+        BigInt::new(n < 0, vec![value as u32, (value >> 32) as u32])
+    }
+
+    fn compare_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+        a.len()
+            .cmp(&b.len())
+            .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+    }
+
+    fn add(&self, other: &BigInt) -> BigInt {
+        let (a, b) = (&self.magnitude, &other.magnitude);
+        if self.negative == other.negative {
+            let mut sum = vec![];
+            let mut carry = 0_u64;
+            for i in 0..a.len().max(b.len()) {
+                let digit = carry
+                    + a.get(i).copied().unwrap_or(0) as u64
+                    + b.get(i).copied().unwrap_or(0) as u64;
+                sum.push(digit as u32);
+                carry = digit >> 32;
+            }
+            sum.push(carry as u32);
+            BigInt::new(self.negative, sum)
+        } else {
+            // Subtract the smaller magnitude from the larger, which decides the sign
+            let (larger, smaller, negative) = match BigInt::compare_magnitude(a, b) {
+                Ordering::Less => (b, a, other.negative),
+                _ => (a, b, self.negative),
+            };
+            let mut difference = vec![];
+            let mut borrow = 0_i64;
+            for (i, digit) in larger.iter().enumerate() {
+                let mut digit =
+                    *digit as i64 - smaller.get(i).copied().unwrap_or(0) as i64 - borrow;
+                borrow = (digit < 0) as i64;
+                if digit < 0 {
+                    digit += 1 << 32;
+                }
+                difference.push(digit as u32);
+            }
+            BigInt::new(negative, difference)
+        }
+    }
+
+    fn mul_small(&self, factor: i64) -> BigInt {
+        let factor_magnitude = factor.unsigned_abs() as u128;
+        let mut product = vec![];
+        let mut carry = 0_u128;
+        for digit in self.magnitude.iter() {
+            let value = *digit as u128 * factor_magnitude + carry;
+            product.push(value as u32);
+            carry = value >> 32;
+        }
+        while carry > 0 {
+            product.push(carry as u32);
+            carry >>= 32;
+        }
+        BigInt::new(self.negative != (factor < 0), product)
+    }
+
+    /// Divide, rounding towards zero, returning the remainder of the magnitude
+    fn div_small(&self, divisor: u64) -> (BigInt, u64) {
+        let mut quotient = vec![0; self.magnitude.len()];
+        let mut remainder = 0_u128;
+        for (i, digit) in self.magnitude.iter().enumerate().rev() {
+            let value = (remainder << 32) | *digit as u128;
+            quotient[i] = (value / divisor as u128) as u32;
+            remainder = value % divisor as u128;
+        }
+        (BigInt::new(self.negative, quotient), remainder as u64)
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Peel off 9 decimal digits at a time
+        let mut chunks = vec![];
+        let mut rest = BigInt::new(false, self.magnitude.clone());
+        while !rest.magnitude.is_empty() {
+            let (quotient, remainder) = rest.div_small(1_000_000_000);
+            chunks.push(remainder);
+            rest = quotient;
+        }
+        let sign = if self.negative { "-" } else { "" };
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((most_significant, others)) => {
+                write!(f, "{sign}{most_significant}")?;
+                others
+                    .iter()
+                    .rev()
+                    .try_for_each(|chunk| write!(f, "{chunk:09}"))
+            }
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// An exact fraction, always reduced and with a positive denominator. Arithmetic is checked, None
+/// means the result doesn't fit in an i128.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fraction {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Fraction {
+    fn new(numerator: i128, denominator: i128) -> Option<Self> {
+        // i128::MIN can't be negated, which reducing and showing the fraction may need
+        numerator.checked_abs()?;
+        denominator.checked_abs()?;
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Some(Self {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        })
+    }
+
+    fn add(self, other: Fraction) -> Option<Fraction> {
+        let divisor = gcd(self.denominator, other.denominator);
+        Fraction::new(
+            self.numerator
+                .checked_mul(other.denominator / divisor)?
+                .checked_add(other.numerator.checked_mul(self.denominator / divisor)?)?,
+            (self.denominator / divisor).checked_mul(other.denominator)?,
+        )
+    }
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// The polynomial generating a sequence, with the first term at x = 0
+#[derive(Debug)]
+enum Polynomial {
+    /// Coefficients are lowest power first, and there are `degree + 1` of them
+    Stable {
+        degree: usize,
+        coefficients: Vec<Fraction>,
+    },
+    /// The differences never became constant before running out of terms, so any polynomial
+    /// would only be a guess
+    Unstable,
+    /// Stable, but the coefficients don't fit in i128 fractions
+    Overflow { degree: usize },
+}
+
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (degree, coefficients) = match self {
+            Polynomial::Unstable => return write!(f, "unstable"),
+            Polynomial::Overflow { degree } => {
+                return write!(f, "degree {degree}: coefficients overflow an i128")
+            }
+            Polynomial::Stable {
+                degree,
+                coefficients,
+            } => (degree, coefficients),
+        };
+        write!(f, "degree {degree}: f(x) =")?;
+        let mut terms = coefficients
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, coefficient)| coefficient.numerator != 0)
+            .peekable();
+        if terms.peek().is_none() {
+            return write!(f, " 0");
+        }
+        for (i, (power, coefficient)) in terms.enumerate() {
+            let sign = match (i, coefficient.numerator < 0) {
+                (0, false) => "",
+                (0, true) => "-",
+                (_, false) => "+ ",
+                (_, true) => "- ",
+            };
+            let magnitude = Fraction {
+                numerator: coefficient.numerator.abs(),
+                ..*coefficient
+            };
+            let variable = match power {
+                0 => String::new(),
+                1 => "x".to_string(),
+                _ => format!("x^{power}"),
+            };
+            // Fractions are bracketed so that (1/12)x^4 doesn't read as 1/(12x^4)
+            match (magnitude.numerator, magnitude.denominator, power) {
+                (1, 1, 1..) => write!(f, " {sign}{variable}")?,
+                (_, 1, _) | (_, _, 0) => write!(f, " {sign}{magnitude}{variable}")?,
+                _ => write!(f, " {sign}({magnitude}){variable}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Sequence(Vec<isize>);
 
 impl Sequence {
+    /// The differences between consecutive terms, or None once they're all zero or there are too
+    /// few terms left to take differences of
     fn next_sequence(&self) -> Option<Self> {
-        if self.0.iter().all(|n| *n == 0) {
+        if self.0.len() < 2 || self.0.iter().all(|n| *n == 0) {
             None
         } else {
             Some(Self(self.0.windows(2).map(|w| w[1] - w[0]).collect()))
@@ -20,9 +259,86 @@ impl Sequence {
 
         expanded_sequences
     }
+
+    /// The first term of every row of the difference table, Δ⁰f(0), Δ¹f(0), ...
+    fn leading_differences(&self) -> Vec<isize> {
+        self.clone()
+            .expand()
+            .iter()
+            .filter_map(|sequence| sequence.0.first().copied())
+            .collect()
+    }
+
+    /// Recover the polynomial from the leading differences, expanding every C(x, j) of Newton's
+    /// formula into powers of x
+    fn polynomial(&self) -> Polynomial {
+        let rows = self.clone().expand();
+        // Expansion only ends on a nonzero row when it ran out of terms to difference
+        if rows.last().unwrap().0.iter().any(|n| *n != 0) {
+            return Polynomial::Unstable;
+        }
+        // The last row is all zeros, the one before it is the constant one
+        let degree = rows.len().saturating_sub(2);
+
+        match self.coefficients(degree) {
+            Some(coefficients) => Polynomial::Stable {
+                degree,
+                coefficients,
+            },
+            None => Polynomial::Overflow { degree },
+        }
+    }
+
+    /// Coefficients of the polynomial of the given degree through the terms, lowest power first.
+    /// None if any of them, or any step along the way, doesn't fit in an i128
+    fn coefficients(&self, degree: usize) -> Option<Vec<Fraction>> {
+        let mut coefficients = vec![Fraction::new(0, 1)?; degree + 1];
+        // Coefficients of the falling factorial x(x − 1)...(x − j + 1), and j!
+        let mut falling = vec![1_i128];
+        let mut factorial = 1_i128;
+        // The trailing zero row adds nothing
+        let differences = self.leading_differences().into_iter().take(degree + 1);
+        for (j, difference) in differences.enumerate() {
+            for (power, coefficient) in falling.iter().enumerate() {
+                let term =
+                    Fraction::new((difference as i128).checked_mul(*coefficient)?, factorial)?;
+                coefficients[power] = coefficients[power].add(term)?;
+            }
+            // Multiply by (x − j)
+            falling.insert(0, 0);
+            for power in 0..falling.len() - 1 {
+                falling[power] =
+                    falling[power].checked_sub((j as i128).checked_mul(falling[power + 1])?)?;
+            }
+            factorial = factorial.checked_mul(j as i128 + 1)?;
+        }
+        Some(coefficients)
+    }
+
+    /// The term `offset` steps after the last one, or before the first one for negative
+    /// offsets. Uses Newton's forward difference formula, f(x) = Σ C(x, j)·Δʲf(0), so only
+    /// the leading differences are needed however far away the term is.
+    fn extrapolate(&self, offset: isize) -> BigInt {
+        // Position of the term, where the first term is at 0
+        let x = if offset >= 0 {
+            self.0.len() as i64 - 1 + offset as i64
+        } else {
+            offset as i64
+        };
+        let mut binomial = BigInt::from_i64(1);
+        let mut value = BigInt::default();
+        for (j, difference) in self.leading_differences().into_iter().enumerate() {
+            value = value.add(&binomial.mul_small(difference as i64));
+            // C(x, j + 1) = C(x, j)·(x − j) / (j + 1), the division is always exact
+            binomial = binomial.mul_small(x - j as i64).div_small(j as u64 + 1).0;
+        }
+        value
+    }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
     let input: Vec<_> = include_str!("./input")
         .split('\n')
         .filter(|line| !line.is_empty())
@@ -40,30 +356,139 @@ fn main() {
         .collect();
 
     // Part one
-    let sum: isize = sequences
-        .clone()
-        .into_iter()
-        .map(|sequence| {
-            let expanded_sequences = sequence.expand();
-            expanded_sequences
-                .into_iter()
-                .fold(0, |num, seq| num + seq.0.last().unwrap())
-        })
-        .sum();
+    let sum = sequences.iter().fold(BigInt::default(), |sum, sequence| {
+        sum.add(&sequence.extrapolate(1))
+    });
     println!("{sum}");
 
     // Part two
-    let sum: isize = sequences
-        .into_iter()
-        .map(|sequence| {
-            let expanded_sequences = sequence.expand();
-            expanded_sequences
-                .into_iter()
-                // This one has to be reversed because the order is important in subtraction.
-                // Ie. (-2)-3 is not the same as 3-(-2)
-                .rev()
-                .fold(0, |num, seq| seq.0.first().unwrap() - num)
-        })
-        .sum();
+    let sum = sequences.iter().fold(BigInt::default(), |sum, sequence| {
+        sum.add(&sequence.extrapolate(-1))
+    });
     println!("{sum}");
+
+    // Any other offset, eg. `--offset 1000` or `--offset -50`
+    if let Some(offset) = arg_value(&args, "--offset") {
+        let sum = sequences.iter().fold(BigInt::default(), |sum, sequence| {
+            sum.add(&sequence.extrapolate(offset))
+        });
+        println!("{sum}");
+    }
+
+    if args.iter().any(|arg| arg == "--polynomials") {
+        let (mut unstable, mut overflowing) = (0, 0);
+        for (i, sequence) in sequences.iter().enumerate() {
+            let polynomial = sequence.polynomial();
+            match polynomial {
+                Polynomial::Unstable => unstable += 1,
+                Polynomial::Overflow { .. } => overflowing += 1,
+                Polynomial::Stable { .. } => {}
+            }
+            println!("{}: {polynomial}", i + 1);
+        }
+        println!(
+            "{unstable} of {} sequences are unstable, {overflowing} overflow",
+            sequences.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_sequences() -> Vec<Sequence> {
+        include_str!("./input")
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| Sequence(line.split(' ').map(|num| num.parse().unwrap()).collect()))
+            .collect()
+    }
+
+    /// Builds a BigInt straight from its digits, not through any of the arithmetic under test
+    fn big(n: i128) -> BigInt {
+        let magnitude = n.unsigned_abs();
+        let digits = (0..4).map(|i| (magnitude >> (32 * i)) as u32).collect();
+        BigInt::new(n < 0, digits)
+    }
+
+    /// Values around the points where digits carry or borrow, of both signs
+    fn boundaries() -> Vec<i128> {
+        let mut values = vec![0, 1, 2, 9, 10, 999_999_999, 1_000_000_000];
+        for shift in [32, 64, 96] {
+            let power = 1_i128 << shift;
+            values.extend([power - 1, power, power + 1]);
+        }
+        values.extend([i64::MAX as i128, i128::MAX / 3]);
+        let negated: Vec<i128> = values.iter().map(|n| -n).collect();
+        values.extend(negated);
+        values
+    }
+
+    #[test]
+    fn extrapolate_matches_difference_table() {
+        for sequence in input_sequences() {
+            let rows = sequence.clone().expand();
+            let next: isize = rows.iter().map(|row| row.0.last().unwrap()).sum();
+            let previous = rows
+                .iter()
+                .rev()
+                .fold(0, |num, row| row.0.first().unwrap() - num);
+            assert_eq!(sequence.extrapolate(1).to_string(), next.to_string());
+            assert_eq!(sequence.extrapolate(-1).to_string(), previous.to_string());
+        }
+    }
+
+    #[test]
+    fn polynomial_display() {
+        let polynomial = |terms: &[isize]| Sequence(terms.to_vec()).polynomial().to_string();
+        assert_eq!(polynomial(&[0, 1, 4, 9]), "degree 2: f(x) = x^2");
+        assert_eq!(
+            polynomial(&[1, 2, 4, 7, 11]),
+            "degree 2: f(x) = (1/2)x^2 + (1/2)x + 1"
+        );
+        assert_eq!(polynomial(&[3, -1, -5]), "degree 1: f(x) = -4x + 3");
+        assert_eq!(polynomial(&[1, 2, 4]), "unstable");
+    }
+
+    #[test]
+    fn big_int_display() {
+        for n in boundaries() {
+            assert_eq!(big(n).to_string(), n.to_string());
+        }
+        assert_eq!(BigInt::from_i64(i64::MIN).to_string(), i64::MIN.to_string());
+    }
+
+    #[test]
+    fn big_int_add() {
+        for a in boundaries() {
+            for b in boundaries() {
+                if let Some(sum) = a.checked_add(b) {
+                    assert_eq!(big(a).add(&big(b)), big(sum), "{a} + {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn big_int_mul_small() {
+        for a in boundaries() {
+            for factor in [0, 1, -1, 7, -10, 1 << 32, i64::MAX, i64::MIN] {
+                if let Some(product) = a.checked_mul(factor as i128) {
+                    assert_eq!(big(a).mul_small(factor), big(product), "{a} * {factor}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn big_int_div_small() {
+        for a in boundaries() {
+            for divisor in [1, 2, 3, 10, 1_000_000_000, u32::MAX as u64 + 1, u64::MAX] {
+                let (quotient, remainder) = big(a).div_small(divisor);
+                assert_eq!(quotient, big(a / divisor as i128), "{a} / {divisor}");
+                assert_eq!(remainder as u128, a.unsigned_abs() % divisor as u128);
+            }
+        }
+    }
 }
@@ -0,0 +1,18 @@
+use std::{fmt::Display, str::FromStr};
+
+/// Value following `flag` on the command line, eg. `--scoring linear`, parsed into whatever the
+/// caller needs. Panics naming the flag when the value doesn't parse.
+pub fn arg_value<T>(args: &[String], flag: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|error| panic!("Invalid value {value:?} for {flag}: {error}"))
+        })
+}